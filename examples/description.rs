@@ -6,7 +6,8 @@ use metrics_exporter_cli::CliRegister;
 fn main() {
     let mut register = CliRegister::install().expect("Error installing register");
     std::thread::spawn(move || {
-        register.print_loop();
+        let err = register.print_loop();
+        eprintln!("Error printing metrics: {}", err);
     });
 
     let absolute = register_counter!("absolute");
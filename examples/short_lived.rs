@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+use metrics::increment_counter;
+use metrics_exporter_cli::CliRegister;
+
+fn main() {
+    let register = CliRegister::install()
+        .expect("Error installing register")
+        .with_interval(Duration::from_millis(250))
+        .with_final_row(true);
+    increment_counter!("processed");
+    let print_loop = register.spawn();
+
+    for _ in 0..10 {
+        increment_counter!("processed");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    // Print the last line and wait for the printing thread to terminate
+    print_loop.stop().expect("Error printing metrics");
+}
@@ -10,12 +10,13 @@ fn main() {
     std::thread::spawn(move || {
         std::thread::sleep(Duration::from_millis(500));
         // print_loop() is the same as:
-        // println!("{}", register.header());
+        // register.print_header()?;
         // loop {
-        //     println!("{}", register.status());
+        //     register.print_status()?;
         //     std::thread::sleep(Duration::from_secs(1));
         // }
-        register.print_loop();
+        let err = register.print_loop();
+        eprintln!("Error printing metrics: {}", err);
     });
 
     let mut rng = thread_rng();
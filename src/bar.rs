@@ -0,0 +1,137 @@
+/// Eighths of a cell, from 1/8 to 7/8
+const PARTIAL_BLOCKS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+const FULL_BLOCK: char = '█';
+/// Columns reserved for the value displayed after the bar, e.g. `12.3ms`
+const VALUE_WIDTH: usize = 6;
+
+/// How [`crate::DisplayKind::Histogram`] fields draw their values: a bar of
+/// a fixed width, proportional to the value.
+///
+/// ```
+/// use metrics_exporter_cli::Bar;
+///
+/// let bar = Bar::new().with_width(4).with_max(8.0);
+/// assert_eq!(bar.render(3.0), "█▌  ");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bar {
+    width: usize,
+    /// Value of a full bar, the largest value displayed so far if None
+    max: Option<f64>,
+    log_scale: bool,
+    annotated: bool,
+}
+
+impl Default for Bar {
+    fn default() -> Self {
+        Self {
+            width: 10,
+            max: None,
+            log_scale: false,
+            annotated: false,
+        }
+    }
+}
+
+impl Bar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of characters of a full bar. Defaults to 10.
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Set the value of a full bar. By default, it's the largest value
+    /// displayed so far by the field.
+    pub fn with_max(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Scale the bar with the logarithm of the value, so that small values
+    /// stay visible next to large ones.
+    pub fn with_log_scale(mut self, log_scale: bool) -> Self {
+        self.log_scale = log_scale;
+        self
+    }
+
+    /// Display the value after the bar, right-aligned in 6 more characters
+    /// so that the field keeps its width.
+    pub fn with_value(mut self, annotated: bool) -> Self {
+        self.annotated = annotated;
+        self
+    }
+
+    /// Number of characters of the bar and its value, if displayed.
+    pub(crate) fn width(&self) -> usize {
+        match self.annotated {
+            true => self.width + 1 + VALUE_WIDTH,
+            false => self.width,
+        }
+    }
+
+    pub(crate) fn annotated(&self) -> bool {
+        self.annotated
+    }
+
+    /// Append the formatted value to a rendered bar.
+    pub(crate) fn annotate(&self, bar: &mut String, value: &str) {
+        bar.push_str(&format!(" {:>VALUE_WIDTH$}", value));
+    }
+
+    /// Draw the bar of the given value, using the configured maximum.
+    pub fn render(&self, value: f64) -> String {
+        self.render_with_max(value, self.max.unwrap_or(value))
+    }
+
+    /// Draw the bar of the given value, `max` being used when no maximum was
+    /// configured. Negative and missing (NaN) values have an empty bar.
+    pub(crate) fn render_with_max(&self, value: f64, max: f64) -> String {
+        let max = self.max.unwrap_or(max);
+        let fraction = if self.log_scale {
+            value.ln_1p() / max.ln_1p()
+        } else {
+            value / max
+        };
+        let fraction = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        let eighths = (fraction * self.width as f64 * 8.0).round() as usize;
+        let mut output: String = std::iter::repeat_n(FULL_BLOCK, eighths / 8).collect();
+        let partial = eighths % 8;
+        if partial > 0 {
+            output.push(PARTIAL_BLOCKS[partial - 1]);
+        }
+        let len = output.chars().count();
+        output.extend(std::iter::repeat_n(' ', self.width - len));
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bars() {
+        let bar = Bar::new().with_width(4);
+        assert_eq!(bar.render_with_max(1.0, 1.0), "████");
+        assert_eq!(bar.render_with_max(1.0, 3.0), "█▍  ");
+        assert_eq!(bar.render_with_max(-1.0, 3.0), "    ");
+        assert_eq!(bar.render_with_max(f64::NAN, 3.0), "    ");
+        assert_eq!(bar.render_with_max(0.0, 0.0), "    ");
+        assert_eq!(bar.with_max(2.0).render_with_max(4.0, 8.0), "████");
+    }
+
+    #[test]
+    fn log_scale() {
+        let bar = Bar::new().with_width(4).with_log_scale(true);
+        assert_eq!(bar.render_with_max(9.0, 99.0), "██  ");
+        assert_eq!(bar.render_with_max(99.0, 99.0), "████");
+    }
+}
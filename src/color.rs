@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::Value;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const DIM: &str = "\x1b[2m";
+pub(crate) const RESET: &str = "\x1b[0m";
+
+/// Whether the table is colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorChoice {
+    /// Colors are used when writing to a terminal and the `NO_COLOR`
+    /// environment variable is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decide whether to use colors when writing to an output which is, or
+    /// isn't, a terminal.
+    pub(crate) fn enabled(&self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                terminal && std::env::var_os("NO_COLOR").is_none_or(|x| x.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Rules coloring the cells of a field according to their value. When
+/// several rules match, the first one of this list wins:
+/// - values above the critical threshold are bold red
+/// - values above the warning threshold are yellow
+/// - zeros are dimmed
+/// - with trend colors, positive values are green and negative ones red,
+///   which shows increases and decreases in [`crate::DisplayKind::Difference`]
+///   and [`crate::DisplayKind::Rate`] columns
+///
+/// Rules can also be set with metric labels: `warning` and `critical` for the
+/// thresholds, and `color` with a comma separated list of `trend` and `dim-zero`.
+///
+/// ```
+/// use metrics_exporter_cli::ColorRules;
+///
+/// let rules = ColorRules::new().with_warning(80.0).with_critical(95.0).with_dim_zero(true);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ColorRules {
+    warning: Option<f64>,
+    critical: Option<f64>,
+    dim_zero: bool,
+    trend: bool,
+}
+
+impl ColorRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Color values greater than or equal to `threshold` in yellow.
+    pub fn with_warning(mut self, threshold: f64) -> Self {
+        self.warning = Some(threshold);
+        self
+    }
+
+    /// Color values greater than or equal to `threshold` in bold red.
+    pub fn with_critical(mut self, threshold: f64) -> Self {
+        self.critical = Some(threshold);
+        self
+    }
+
+    /// Dim zeros, so that non zero values stand out.
+    pub fn with_dim_zero(mut self, dim_zero: bool) -> Self {
+        self.dim_zero = dim_zero;
+        self
+    }
+
+    /// Color positive values in green and negative values in red.
+    pub fn with_trend(mut self, trend: bool) -> Self {
+        self.trend = trend;
+        self
+    }
+
+    /// Read the rules from the `warning`, `critical` and `color` labels of a
+    /// metric. Return None if there is no rule.
+    pub(crate) fn from_labels(labels: &HashMap<String, String>) -> Option<Self> {
+        let mut rules = Self::new();
+        let threshold = |name| labels.get(name).and_then(|x| x.parse().ok());
+        rules.warning = threshold("warning");
+        rules.critical = threshold("critical");
+        for flag in labels.get("color").into_iter().flat_map(|x| x.split(',')) {
+            match flag.trim() {
+                "trend" => rules.trend = true,
+                "dim-zero" => rules.dim_zero = true,
+                _ => {}
+            }
+        }
+        (rules != Self::default()).then_some(rules)
+    }
+
+    /// ANSI sequence starting the color of the value, if any.
+    pub(crate) fn color_of(&self, value: &Value) -> Option<&'static str> {
+        let x = match value {
+            Value::Int(x) => *x as f64,
+            Value::F64(x) => *x,
+            Value::Empty => return None,
+        };
+        if self.critical.is_some_and(|threshold| x >= threshold) {
+            Some(BOLD_RED)
+        } else if self.warning.is_some_and(|threshold| x >= threshold) {
+            Some(YELLOW)
+        } else if self.dim_zero && x == 0.0 {
+            Some(DIM)
+        } else if self.trend && x > 0.0 {
+            Some(GREEN)
+        } else if self.trend && x < 0.0 {
+            Some(RED)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let rules = ColorRules::new()
+            .with_warning(10.0)
+            .with_critical(20.0)
+            .with_dim_zero(true)
+            .with_trend(true);
+        assert_eq!(rules.color_of(&Value::Int(25)), Some(BOLD_RED));
+        assert_eq!(rules.color_of(&Value::F64(10.0)), Some(YELLOW));
+        assert_eq!(rules.color_of(&Value::Int(0)), Some(DIM));
+        assert_eq!(rules.color_of(&Value::Int(3)), Some(GREEN));
+        assert_eq!(rules.color_of(&Value::Int(-3)), Some(RED));
+        assert_eq!(rules.color_of(&Value::Empty), None);
+        assert_eq!(ColorRules::new().color_of(&Value::Int(25)), None);
+    }
+
+    #[test]
+    fn labels() {
+        let labels = HashMap::from([
+            ("warning".to_string(), "10".to_string()),
+            ("color".to_string(), "trend, dim-zero".to_string()),
+        ]);
+        assert_eq!(
+            ColorRules::from_labels(&labels),
+            Some(
+                ColorRules::new()
+                    .with_warning(10.0)
+                    .with_trend(true)
+                    .with_dim_zero(true)
+            )
+        );
+        assert_eq!(ColorRules::from_labels(&HashMap::new()), None);
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    CliRegister, ColorChoice, DisplayKind, Filter, Orientation, OutputFormat, Pattern, Prefixes,
+};
+
+/// Environment variable read by [`Config::from_env`].
+pub const CONFIG_ENV: &str = "METRICS_CLI";
+
+/// Settings of a [`CliRegister`], read from a TOML file or from the
+/// `METRICS_CLI` environment variable, so that the table can be changed
+/// without recompiling. Settings which are not set keep the values of the
+/// register.
+///
+/// ```toml
+/// interval = 0.5                      # seconds between two lines
+/// format = "table"                    # table, csv, tsv, json-lines or top
+/// orientation = "vertical"            # horizontal or vertical
+/// output = "stderr"                   # stdout, stderr or the path of a file
+/// filter = ["http.*", "!method=OPTIONS"]  # see Filter::parse
+/// order = ["http.*", "db.*"]          # globs of the metrics displayed first
+/// precision = 1                       # maximum number of decimals
+/// prefixes = "decimal"                # binary (KiB) or decimal (kB)
+/// color = "never"                     # auto, always or never
+///
+/// [kinds]                             # display kind of the matching metrics
+/// "http.requests" = "rate"            # number, difference, rate, histogram or sparkline
+/// "db.pool.*" = "sparkline"
+/// ```
+///
+/// ```no_run
+/// use metrics_exporter_cli::{CliRegister, Config};
+///
+/// let config = Config::from_file("metrics.toml")?.merge(Config::from_env()?);
+/// let register = CliRegister::install()?.with_config(&config)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Seconds between two lines
+    interval: Option<f64>,
+    format: Option<OutputFormat>,
+    orientation: Option<Orientation>,
+    /// `stdout`, `stderr` or the path of a file
+    output: Option<String>,
+    filter: Option<Patterns>,
+    /// Globs of the metrics displayed first, in order
+    order: Option<Patterns>,
+    /// Display kind of the metrics matching each glob
+    kinds: BTreeMap<String, DisplayKind>,
+    precision: Option<usize>,
+    prefixes: Option<Prefixes>,
+    color: Option<ColorChoice>,
+}
+
+/// Comma separated patterns, or a list of them
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Patterns {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Patterns {
+    fn iter(&self) -> impl Iterator<Item = &str> {
+        let patterns = match self {
+            Patterns::One(patterns) => std::slice::from_ref(patterns),
+            Patterns::Many(patterns) => patterns,
+        };
+        patterns
+            .iter()
+            .flat_map(|x| x.split(','))
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a TOML configuration.
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        toml::from_str(toml).map_err(|error| ConfigError::Parse {
+            origin: "configuration".to_string(),
+            error,
+        })
+    }
+
+    /// Read a TOML configuration file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let toml = fs::read_to_string(path).map_err(|error| ConfigError::Read {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        toml::from_str(&toml).map_err(|error| ConfigError::Parse {
+            origin: path.display().to_string(),
+            error,
+        })
+    }
+
+    /// Read the `METRICS_CLI` environment variable: settings of the TOML
+    /// configuration separated by semicolons, with values which are not
+    /// numbers or booleans written without quotes, e.g.
+    /// `format=csv;interval=0.5;filter=http.*,!*.debug;kinds.db.pool.*=sparkline`.
+    /// A `file=path` setting reads the configuration file first, the other
+    /// settings overriding it. The configuration is empty if the variable
+    /// isn't set.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        match std::env::var(CONFIG_ENV) {
+            Ok(settings) => Self::from_settings(&settings),
+            Err(_) => Ok(Self::new()),
+        }
+    }
+
+    /// Parse the settings of [`Config::from_env`].
+    fn from_settings(settings: &str) -> Result<Self, ConfigError> {
+        let mut file = None;
+        let mut table = toml::Table::new();
+        for setting in settings.split(';').map(str::trim) {
+            if setting.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = setting.split_once('=') else {
+                return Err(ConfigError::Invalid {
+                    setting: setting.to_string(),
+                    message: format!("expected `key=value` in {}", CONFIG_ENV),
+                });
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key == "file" {
+                file = Some(value);
+                continue;
+            }
+            // Numbers and booleans are parsed, anything else is a string
+            let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+                .ok()
+                .and_then(|mut x| x.remove("value"))
+                .unwrap_or_else(|| toml::Value::String(value.to_string()));
+            match key.split_once('.') {
+                None => _ = table.insert(key.to_string(), value),
+                Some((table_key, key)) => {
+                    let entry = table
+                        .entry(table_key)
+                        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+                    if let toml::Value::Table(entry) = entry {
+                        entry.insert(key.to_string(), value);
+                    }
+                }
+            }
+        }
+        let config: Self =
+            toml::Value::Table(table)
+                .try_into()
+                .map_err(|error| ConfigError::Parse {
+                    origin: CONFIG_ENV.to_string(),
+                    error,
+                })?;
+        match file {
+            Some(path) => Ok(Self::from_file(path)?.merge(config)),
+            None => Ok(config),
+        }
+    }
+
+    /// Combine two configurations, the settings of `other` overriding the
+    /// ones of `self`.
+    pub fn merge(mut self, other: Config) -> Self {
+        self.interval = other.interval.or(self.interval);
+        self.format = other.format.or(self.format);
+        self.orientation = other.orientation.or(self.orientation);
+        self.output = other.output.or(self.output);
+        self.filter = other.filter.or(self.filter);
+        self.order = other.order.or(self.order);
+        self.kinds.extend(other.kinds);
+        self.precision = other.precision.or(self.precision);
+        self.prefixes = other.prefixes.or(self.prefixes);
+        self.color = other.color.or(self.color);
+        self
+    }
+
+    /// Apply the settings to the register.
+    pub(crate) fn apply(&self, mut register: CliRegister) -> Result<CliRegister, ConfigError> {
+        if let Some(seconds) = self.interval {
+            // Negative, zero, non-finite or too large durations
+            let interval = Duration::try_from_secs_f64(seconds)
+                .ok()
+                .filter(|interval| !interval.is_zero())
+                .ok_or_else(|| ConfigError::Invalid {
+                    setting: "interval".to_string(),
+                    message: format!("expected a positive number of seconds, got {}", seconds),
+                })?;
+            register = register.with_interval(interval);
+        }
+        if let Some(format) = self.format {
+            register = register.with_format(format);
+        }
+        if let Some(orientation) = self.orientation {
+            register = register.with_orientation(orientation);
+        }
+        match self.output.as_deref() {
+            None | Some("stdout") => {}
+            Some("stderr") => register = register.with_output(io::stderr()),
+            Some(path) => {
+                let file = fs::File::create(path).map_err(|error| ConfigError::Output {
+                    path: PathBuf::from(path),
+                    error,
+                })?;
+                register = register.with_output(file);
+            }
+        }
+        if let Some(patterns) = &self.filter {
+            let mut filter = Filter::new();
+            for pattern in patterns.iter() {
+                filter = filter.add(pattern).map_err(|error| ConfigError::Pattern {
+                    pattern: pattern.to_string(),
+                    error,
+                })?;
+            }
+            register = register.with_filter(filter);
+        }
+        if let Some(patterns) = &self.order {
+            register = register.with_column_order(patterns.iter().map(Pattern::glob).collect());
+        }
+        // The most specific, i.e. longest, glob wins
+        let mut kinds: Vec<_> = self.kinds.iter().collect();
+        kinds.sort_by_key(|(glob, _)| std::cmp::Reverse(glob.len()));
+        for (glob, kind) in kinds {
+            register = register.with_display_kind(Pattern::glob(glob), kind.clone());
+        }
+        if self.precision.is_some() || self.prefixes.is_some() {
+            let mut number_format = register.number_format;
+            if let Some(precision) = self.precision {
+                number_format = number_format.with_precision(precision);
+            }
+            if let Some(prefixes) = self.prefixes {
+                number_format = number_format.with_prefixes(prefixes);
+            }
+            register = register.with_number_format(number_format);
+        }
+        if let Some(color) = self.color {
+            register = register.with_color(color);
+        }
+        Ok(register)
+    }
+}
+
+/// Error of an invalid [`Config`].
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file can't be read
+    Read { path: PathBuf, error: io::Error },
+    /// The configuration isn't valid TOML, or has unknown or invalid settings
+    Parse {
+        /// Path of the file or name of the environment variable
+        origin: String,
+        error: toml::de::Error,
+    },
+    /// A setting has an invalid value
+    Invalid { setting: String, message: String },
+    /// A filter pattern isn't a valid regex
+    Pattern {
+        pattern: String,
+        error: regex::Error,
+    },
+    /// The output file can't be created
+    Output { path: PathBuf, error: io::Error },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => {
+                write!(f, "cannot read {}: {}", path.display(), error)
+            }
+            ConfigError::Parse { origin, error } => {
+                write!(f, "invalid {}: {}", origin, error.message())
+            }
+            ConfigError::Invalid { setting, message } => {
+                write!(f, "invalid setting `{}`: {}", setting, message)
+            }
+            ConfigError::Pattern { pattern, error } => {
+                write!(f, "invalid filter pattern `{}`: {}", pattern, error)
+            }
+            ConfigError::Output { path, error } => {
+                write!(f, "cannot create output {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Read { error, .. } | ConfigError::Output { error, .. } => Some(error),
+            ConfigError::Parse { error, .. } => Some(error),
+            ConfigError::Pattern { error, .. } => Some(error),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_and_settings() {
+        let file = Config::from_toml(
+            r#"
+            interval = 2
+            format = "csv"
+            filter = ["http.*", "!method=OPTIONS"]
+            precision = 1
+
+            [kinds]
+            "db.pool.*" = "sparkline"
+            "#,
+        )
+        .unwrap();
+        let env =
+            Config::from_settings("format=json-lines; interval=0.5; kinds.http.requests=rate")
+                .unwrap();
+        let config = file.merge(env);
+        assert_eq!(config.interval, Some(0.5));
+        assert_eq!(config.format, Some(OutputFormat::JsonLines));
+        assert_eq!(config.precision, Some(1));
+        assert_eq!(
+            config.filter.as_ref().unwrap().iter().collect::<Vec<_>>(),
+            ["http.*", "!method=OPTIONS"]
+        );
+        assert_eq!(
+            config.kinds,
+            BTreeMap::from([
+                ("db.pool.*".to_string(), DisplayKind::Sparkline),
+                ("http.requests".to_string(), DisplayKind::Rate),
+            ])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |result: Result<Config, ConfigError>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(Config::from_settings("format=xml")),
+            "invalid METRICS_CLI: unknown variant `xml`, expected one of \
+             `table`, `csv`, `tsv`, `json-lines`, `top`"
+        );
+        assert_eq!(
+            error(Config::from_settings("intervals=1")),
+            "invalid METRICS_CLI: unknown field `intervals`, expected one of \
+             `interval`, `format`, `orientation`, `output`, `filter`, `order`, \
+             `kinds`, `precision`, `prefixes`, `color`"
+        );
+        assert_eq!(
+            error(Config::from_settings("csv")),
+            "invalid setting `csv`: expected `key=value` in METRICS_CLI"
+        );
+        assert!(error(Config::from_file("missing.toml")).starts_with("cannot read missing.toml"));
+        let register = CliRegister::new(Default::default());
+        let config = Config::from_settings("interval=-1").unwrap();
+        assert_eq!(
+            config.apply(register).err().unwrap().to_string(),
+            "invalid setting `interval`: expected a positive number of seconds, got -1"
+        );
+        let register = CliRegister::new(Default::default());
+        let config = Config::from_toml("interval = 1e30").unwrap();
+        assert_eq!(
+            config.apply(register).err().unwrap().to_string(),
+            "invalid setting `interval`: expected a positive number of seconds, got 1000000000000000000000000000000"
+        );
+        let register = CliRegister::new(Default::default());
+        let config = Config::from_settings("filter=~(").unwrap();
+        assert!(config
+            .apply(register)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("invalid filter pattern `~(`"));
+    }
+}
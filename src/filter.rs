@@ -0,0 +1,204 @@
+use metrics::Key;
+use regex::Regex;
+
+/// Environment variable read by [`Filter::from_env`].
+pub const FILTER_ENV: &str = "METRICS_CLI_FILTER";
+
+/// Pattern matching the name of a metric, or the value of one of its labels.
+///
+/// ```
+/// use metrics_exporter_cli::Pattern;
+///
+/// let http = Pattern::glob("http.*");
+/// let errors = Pattern::regex("^(4|5)[0-9]{2}$")?.on_label("status");
+/// # Ok::<(), regex::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    /// Label whose value is matched, the name of the metric if None
+    label: Option<String>,
+    regex: Regex,
+}
+
+impl Pattern {
+    /// Match the whole text with a glob: `*` matches any characters,
+    /// including dots, and `?` a single character.
+    pub fn glob(glob: &str) -> Self {
+        let mut regex = String::from("^");
+        for c in glob.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        regex.push('$');
+        Self {
+            label: None,
+            regex: Regex::new(&regex).expect("invalid escaped glob"),
+        }
+    }
+
+    /// Match a regular expression anywhere in the text, unless anchored with
+    /// `^` and `$`.
+    pub fn regex(regex: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            label: None,
+            regex: Regex::new(regex)?,
+        })
+    }
+
+    /// Match the value of the label `label` instead of the name. Metrics
+    /// without this label don't match.
+    pub fn on_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    pub(crate) fn matches(&self, key: &Key) -> bool {
+        match &self.label {
+            None => self.regex.is_match(key.name()),
+            Some(label) => key
+                .labels()
+                .any(|x| x.key() == label && self.regex.is_match(x.value())),
+        }
+    }
+
+    /// Parse a pattern of [`Filter::parse`], without the `!` prefix.
+    fn parse(pattern: &str) -> Result<Self, regex::Error> {
+        let (label, pattern) = match pattern.find(['=', '~']) {
+            // A label name is an identifier, while names and globs can
+            // contain other characters such as dots
+            Some(i)
+                if i > 0
+                    && pattern[..i]
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                (Some(&pattern[..i]), &pattern[i..])
+            }
+            _ => (None, pattern),
+        };
+        let pattern = match pattern.strip_prefix('~') {
+            Some(regex) => Self::regex(regex)?,
+            None => Self::glob(pattern.strip_prefix('=').unwrap_or(pattern)),
+        };
+        Ok(match label {
+            Some(label) => pattern.on_label(label),
+            None => pattern,
+        })
+    }
+}
+
+/// Which metrics are displayed: those matching any of the included patterns,
+/// or all metrics if there is none, and none of the excluded patterns.
+///
+/// ```
+/// use metrics_exporter_cli::{Filter, Pattern};
+///
+/// let filter = Filter::new()
+///     .include(Pattern::glob("http.*"))
+///     .include(Pattern::glob("db.pool.*"))
+///     .exclude(Pattern::glob("GET").on_label("method"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Display the metrics matching `pattern`, along with the ones matching
+    /// other included patterns.
+    pub fn include(mut self, pattern: Pattern) -> Self {
+        self.include.push(pattern);
+        self
+    }
+
+    /// Hide the metrics matching `pattern`, even if they are included.
+    pub fn exclude(mut self, pattern: Pattern) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Parse a comma separated list of patterns, e.g. `http.*,!*.debug`:
+    /// - `glob` includes the metrics whose name matches the glob
+    /// - `~regex` includes the metrics whose name matches the regex
+    /// - `label=glob` and `label~regex` match the value of a label instead
+    /// - a `!` prefix excludes the metrics instead of including them
+    pub fn parse(patterns: &str) -> Result<Self, regex::Error> {
+        let mut filter = Self::new();
+        for pattern in patterns.split(',').map(str::trim) {
+            filter = filter.add(pattern)?;
+        }
+        Ok(filter)
+    }
+
+    /// Include or exclude a single pattern of [`Filter::parse`].
+    pub(crate) fn add(self, pattern: &str) -> Result<Self, regex::Error> {
+        Ok(match pattern.strip_prefix('!') {
+            Some(pattern) => self.exclude(Pattern::parse(pattern)?),
+            None if pattern.is_empty() => self,
+            None => self.include(Pattern::parse(pattern)?),
+        })
+    }
+
+    /// Parse the patterns of the `METRICS_CLI_FILTER` environment variable
+    /// with [`Filter::parse`]. All metrics are displayed if it isn't set.
+    pub fn from_env() -> Result<Self, regex::Error> {
+        match std::env::var(FILTER_ENV) {
+            Ok(patterns) => Self::parse(&patterns),
+            Err(_) => Ok(Self::new()),
+        }
+    }
+
+    /// Whether the metric is displayed.
+    pub fn matches(&self, key: &Key) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|x| x.matches(key)))
+            && !self.exclude.iter().any(|x| x.matches(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metrics::Label;
+
+    use super::*;
+
+    fn key(name: &'static str, method: &'static str) -> Key {
+        Key::from_parts(name, vec![Label::new("method", method)])
+    }
+
+    #[test]
+    fn patterns() {
+        let filter = Filter::new()
+            .include(Pattern::glob("http.*"))
+            .include(Pattern::regex(r"^db\.pool\.").unwrap())
+            .exclude(Pattern::glob("P?ST").on_label("method"));
+        assert!(filter.matches(&key("http.requests", "GET")));
+        assert!(filter.matches(&key("db.pool.size", "GET")));
+        assert!(!filter.matches(&key("http.requests", "POST")));
+        assert!(!filter.matches(&key("httpXrequests", "GET")));
+        assert!(!filter.matches(&key("cache.db.pool.size", "GET")));
+        assert!(Filter::new().matches(&key("cache", "GET")));
+    }
+
+    #[test]
+    fn parse() {
+        let filter = Filter::parse("http.*, ~^db\\., !method=POST, !*.debug").unwrap();
+        assert!(filter.matches(&key("http.requests", "GET")));
+        assert!(filter.matches(&key("db.pool.size", "PUT")));
+        assert!(!filter.matches(&key("http.requests", "POST")));
+        assert!(!filter.matches(&key("http.debug", "GET")));
+        assert!(!filter.matches(&key("cache.hits", "GET")));
+        let filter = Filter::parse("method~^(GET|PUT)$").unwrap();
+        assert!(filter.matches(&key("cache.hits", "PUT")));
+        assert!(!filter.matches(&key("cache.hits", "POST")));
+        assert!(Filter::parse("~(").is_err());
+        assert!(Filter::parse("").unwrap().matches(&key("cache", "GET")));
+    }
+}
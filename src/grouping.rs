@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use metrics::{Key, Label};
+
+/// Labels which configure how a metric is displayed, rather than
+/// identifying a series.
+const DISPLAY_LABELS: &[&str] = &["view", "color", "warning", "critical"];
+
+type PathFn = dyn Fn(&Key) -> Vec<String> + Send + Sync;
+
+/// How metrics are grouped into columns: the key name is split on separators
+/// into groups and the column name, and label values add grouping levels.
+///
+/// ```
+/// use metrics_exporter_cli::Grouping;
+///
+/// // `http::server::requests{method=GET}` becomes the column `GET` of the
+/// // group `server::requests`, itself part of the group `http`.
+/// let grouping = Grouping::new()
+///     .with_separators(&["::"])
+///     .with_max_depth(1)
+///     .with_labels(&["method"]);
+/// ```
+#[derive(Clone)]
+pub struct Grouping {
+    separators: Vec<String>,
+    /// Maximum number of groups made from the key name
+    max_depth: Option<usize>,
+    /// Labels which become grouping levels, all of them if None
+    labels: Option<Vec<String>>,
+    path: Option<Arc<PathFn>>,
+}
+
+impl Default for Grouping {
+    fn default() -> Self {
+        Self {
+            separators: vec![".".to_string()],
+            max_depth: None,
+            labels: None,
+            path: None,
+        }
+    }
+}
+
+impl Grouping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the separators splitting key names into groups, e.g. `["::", "/"]`.
+    /// Defaults to `"."`. Without separators, the key name is the column name.
+    pub fn with_separators(mut self, separators: &[&str]) -> Self {
+        self.separators = separators
+            .iter()
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect();
+        self
+    }
+
+    /// Limit the number of groups made from the key name. Deeper levels are
+    /// kept in the column name, e.g. `b.c` in group `a` for `a.b.c` with a
+    /// maximum depth of 1.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Choose which labels become grouping levels, in the given order. By
+    /// default all labels do, sorted by key. Labels not listed here are folded
+    /// into the column name, e.g. `GET{status=200}` when grouping by `method`
+    /// only.
+    pub fn with_labels(mut self, labels: &[&str]) -> Self {
+        self.labels = Some(labels.iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    /// Map each key to its groups followed by its column name with a closure,
+    /// instead of splitting the name and using the labels. The key name is the
+    /// column name if the closure returns an empty path.
+    pub fn with_path<F>(mut self, path: F) -> Self
+    where
+        F: Fn(&Key) -> Vec<String> + Send + Sync + 'static,
+    {
+        self.path = Some(Arc::new(path));
+        self
+    }
+
+    /// Groups and field name of the key, followed by the values of the
+    /// grouping labels. The remaining labels are folded into the field name.
+    pub(crate) fn path(&self, key: &Key) -> Vec<String> {
+        if let Some(path) = &self.path {
+            let path = path(key);
+            return if path.is_empty() {
+                vec![key.name().to_string()]
+            } else {
+                path
+            };
+        }
+        let mut path = self.split(key.name());
+        let mut labels: Vec<&Label> = key
+            .labels()
+            .filter(|label| !DISPLAY_LABELS.contains(&label.key()))
+            .collect();
+        labels.sort_by_key(|label| label.key());
+        let mut folded = Vec::new();
+        match &self.labels {
+            None => path.extend(labels.iter().map(|label| label.value().to_string())),
+            Some(grouping_labels) => {
+                for grouping_label in grouping_labels {
+                    if let Some(label) = labels.iter().find(|x| x.key() == grouping_label) {
+                        path.push(label.value().to_string());
+                    }
+                }
+                for label in labels {
+                    if !grouping_labels.iter().any(|x| x == label.key()) {
+                        folded.push(format!("{}={}", label.key(), label.value()));
+                    }
+                }
+            }
+        }
+        if !folded.is_empty() {
+            let field_name = path.last_mut().expect("empty metric path");
+            field_name.push_str(&format!("{{{}}}", folded.join(",")));
+        }
+        path
+    }
+
+    /// Split the name on the separators, the longest one winning when several
+    /// match at the same position, and stop after `max_depth` groups.
+    fn split(&self, name: &str) -> Vec<String> {
+        let mut path = Vec::new();
+        let mut rest = name;
+        while self
+            .max_depth
+            .is_none_or(|max_depth| path.len() < max_depth)
+        {
+            let next = self
+                .separators
+                .iter()
+                .filter_map(|separator| rest.find(separator.as_str()).map(|i| (i, separator)))
+                .min_by_key(|(i, separator)| (*i, usize::MAX - separator.len()));
+            let Some((i, separator)) = next else {
+                break;
+            };
+            path.push(rest[..i].to_string());
+            rest = &rest[i + separator.len()..];
+        }
+        path.push(rest.to_string());
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separators() {
+        let grouping = Grouping::new().with_separators(&[":", "::", "/"]);
+        let key = Key::from_name("http::server/requests");
+        assert_eq!(grouping.path(&key), ["http", "server", "requests"]);
+        let grouping = grouping.with_max_depth(1);
+        assert_eq!(grouping.path(&key), ["http", "server/requests"]);
+        let grouping = Grouping::new().with_separators(&[]);
+        assert_eq!(grouping.path(&key), ["http::server/requests"]);
+    }
+
+    #[test]
+    fn labels() {
+        let labels = vec![
+            Label::new("status", "200"),
+            Label::new("method", "GET"),
+            Label::new("view", "sparkline"),
+        ];
+        let key = Key::from_parts("app.requests", labels);
+        assert_eq!(
+            Grouping::new().path(&key),
+            ["app", "requests", "GET", "200"]
+        );
+        let grouping = Grouping::new().with_labels(&["method"]);
+        assert_eq!(grouping.path(&key), ["app", "requests", "GET{status=200}"]);
+        let grouping = Grouping::new().with_path(|key| {
+            key.labels()
+                .filter(|label| label.key() == "method")
+                .map(|label| label.value().to_string())
+                .chain([key.name().to_string()])
+                .collect()
+        });
+        assert_eq!(grouping.path(&key), ["GET", "app.requests"]);
+        let grouping = Grouping::new().with_path(|_| Vec::new());
+        assert_eq!(grouping.path(&Key::from_name("a.b")), ["a.b"]);
+    }
+}
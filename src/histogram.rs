@@ -0,0 +1,108 @@
+use metrics_util::Quantile;
+
+use crate::table::Value;
+
+/// Quantiles displayed for histograms when none are configured.
+pub const DEFAULT_QUANTILES: &[f64] = &[0.5, 0.9, 0.99];
+
+/// A statistic computed over the histogram samples recorded during a single
+/// row interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stat {
+    Count,
+    Min,
+    Max,
+    Mean,
+    /// The given quantile, between 0.0 and 1.0.
+    Quantile(f64),
+}
+
+impl Stat {
+    /// Column name of the statistic, e.g. `count` or `p99`.
+    pub fn name(&self) -> String {
+        match self {
+            Stat::Count => "count".to_string(),
+            Stat::Min => "min".to_string(),
+            Stat::Max => "max".to_string(),
+            Stat::Mean => "mean".to_string(),
+            Stat::Quantile(q) => Quantile::new(*q).label().to_string(),
+        }
+    }
+}
+
+/// Statistics used to display a histogram, in display order.
+pub(crate) fn stats(quantiles: &[f64]) -> Vec<Stat> {
+    [Stat::Count, Stat::Min, Stat::Max, Stat::Mean]
+        .into_iter()
+        .chain(quantiles.iter().map(|q| Stat::Quantile(*q)))
+        .collect()
+}
+
+/// Histogram samples recorded during a single row interval.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Summary<'a> {
+    /// Sorted samples
+    samples: &'a [f64],
+}
+
+impl<'a> Summary<'a> {
+    /// Sort the samples in place, so that the buffer holding them can be reused.
+    pub fn new(samples: &'a mut [f64]) -> Self {
+        samples.sort_by(|a, b| a.total_cmp(b));
+        Self { samples }
+    }
+
+    /// Compute the given statistic. When no sample was recorded, all
+    /// statistics but the count are empty.
+    pub fn value(&self, stat: Stat) -> Value {
+        let count = self.samples.len();
+        if count == 0 && stat != Stat::Count {
+            return Value::Empty;
+        }
+        match stat {
+            Stat::Count => Value::Int(count as i64),
+            Stat::Min => Value::F64(self.samples[0]),
+            Stat::Max => Value::F64(self.samples[count - 1]),
+            Stat::Mean => Value::F64(self.samples.iter().sum::<f64>() / count as f64),
+            Stat::Quantile(q) => Value::F64(quantile_of_sorted(self.samples, q)),
+        }
+    }
+}
+
+/// Nearest-rank quantile of an already sorted, non empty slice.
+fn quantile_of_sorted(samples: &[f64], quantile: f64) -> f64 {
+    let rank = (quantile.clamp(0.0, 1.0) * samples.len() as f64).ceil() as usize;
+    samples[rank.clamp(1, samples.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let names: Vec<String> = stats(DEFAULT_QUANTILES).iter().map(Stat::name).collect();
+        assert_eq!(names, ["count", "min", "max", "mean", "p50", "p90", "p99"]);
+    }
+
+    #[test]
+    fn summary() {
+        let mut samples: Vec<f64> = (1..=10).rev().map(|x| x as f64).collect();
+        let summary = Summary::new(&mut samples);
+        let values: Vec<String> = stats(&[0.5, 0.9])
+            .into_iter()
+            .map(|stat| summary.value(stat).to_string())
+            .collect();
+        assert_eq!(values, ["10", "1", "10", "5.5", "5", "9"]);
+    }
+
+    #[test]
+    fn empty_summary() {
+        let summary = Summary::new(&mut []);
+        let values: Vec<String> = stats(&[0.5])
+            .into_iter()
+            .map(|stat| summary.value(stat).to_string())
+            .collect();
+        assert_eq!(values, ["0", "-", "-", "-", "-"]);
+    }
+}
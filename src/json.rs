@@ -0,0 +1,83 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use metrics::Key;
+use metrics_util::MetricKind;
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::{
+    histogram::{self, Summary},
+    recorder::Registry,
+    Filter, Value,
+};
+
+/// Build a JSON object with the metrics selected by the filter, e.g.
+/// `{"timestamp":1700000000000,"elapsed":1.5,"metrics":{"requests{method=GET}":3}}`.
+///
+/// Counters are integers, gauges are floats and histograms are objects with
+/// the statistics of the samples recorded since the previous line.
+/// `samples` is a buffer reused to compute the histogram statistics.
+pub(crate) fn json_line(
+    registry: &Registry,
+    quantiles: &[f64],
+    filter: &Filter,
+    samples: &mut Vec<f64>,
+    timestamp: SystemTime,
+    elapsed: Duration,
+) -> String {
+    let mut metrics = Map::new();
+    registry.with_metrics(|items| {
+        for metric in items {
+            let selected = filter.matches(metric.key());
+            let value = match metric.kind() {
+                MetricKind::Counter | MetricKind::Gauge if selected => json_value(metric.value()),
+                MetricKind::Counter | MetricKind::Gauge => continue,
+                MetricKind::Histogram => {
+                    // Drain the samples even if the histogram isn't displayed
+                    samples.clear();
+                    metric.drain_samples(samples);
+                    if !selected {
+                        continue;
+                    }
+                    let summary = Summary::new(samples);
+                    let stats = histogram::stats(quantiles)
+                        .into_iter()
+                        .map(|stat| (stat.name(), json_value(summary.value(stat))))
+                        .collect();
+                    JsonValue::Object(stats)
+                }
+            };
+            metrics.insert(key_name(metric.key()), value);
+        }
+    });
+    let timestamp = timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    json!({
+        "timestamp": timestamp,
+        "elapsed": elapsed.as_secs_f64(),
+        "metrics": metrics,
+    })
+    .to_string()
+}
+
+fn json_value(value: Value) -> JsonValue {
+    match value {
+        Value::Int(x) => json!(x),
+        Value::F64(x) => json!(x),
+        Value::Empty => JsonValue::Null,
+    }
+}
+
+/// Name of the metric followed by its labels, e.g. `requests{method=GET}`.
+fn key_name(key: &Key) -> String {
+    let labels: Vec<String> = key
+        .labels()
+        .map(|label| format!("{}={}", label.key(), label.value()))
+        .collect();
+    if labels.is_empty() {
+        key.name().to_string()
+    } else {
+        format!("{}{{{}}}", key.name(), labels.join(","))
+    }
+}
@@ -1,160 +1,863 @@
+mod bar;
+mod color;
+mod config;
+mod filter;
+mod grouping;
+mod histogram;
+mod json;
+mod print_loop;
+mod recorder;
+mod row;
+mod sparkline;
 mod table;
+mod tui;
+mod units;
 
-use std::{collections::HashMap, time::Duration};
+use std::{
+    any::Any,
+    collections::HashMap,
+    io::{self, IsTerminal, Write},
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
-use metrics::{SetRecorderError, Unit};
-use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshot, Snapshotter};
-use table::{DisplayKind, Table, TableBuilder, Value};
+use histogram::Summary;
+use metrics::{Key, SetRecorderError, SharedString, Unit};
+use metrics_util::MetricKind;
+use recorder::Registry;
+use serde::Deserialize;
 
-pub struct CliRegister {
-    snapshotter: SnapshotterKind,
-    table: Table,
+pub use bar::Bar;
+pub use color::{ColorChoice, ColorRules};
+pub use config::{Config, ConfigError, CONFIG_ENV};
+pub use filter::{Filter, Pattern, FILTER_ENV};
+pub use grouping::Grouping;
+pub use histogram::{Stat, DEFAULT_QUANTILES};
+pub use print_loop::PrintLoop;
+pub use recorder::{CliLayer, CliLayered, CliRecorder};
+pub use row::{Cell, Row};
+pub use table::{DisplayKind, Table, TableBuilder, Value};
+pub use units::{NumberFormat, Prefixes};
+
+/// How the table is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Aligned columns, with groups
+    #[default]
+    Table,
+    /// Comma separated values, with the dotted path of each column as header.
+    /// The header is written once: the columns are those of the first
+    /// metrics, and metrics recorded later are not displayed.
+    Csv,
+    /// Tab separated values, like [`OutputFormat::Csv`]
+    Tsv,
+    /// One JSON object per line with the timestamp, the seconds elapsed since
+    /// the register was installed and the value of each metric. There is no
+    /// header.
+    JsonLines,
+    /// Full-screen view redrawn in place, like `top`: the header, the latest
+    /// line and the minimum, maximum and average of each column since the
+    /// first line. The terminal is restored when the register is dropped, on
+    /// panic and, on Unix, on SIGINT, SIGTERM or SIGHUP. `std::process::exit`
+    /// skips it: stop the loop of [`CliRegister::spawn`] before exiting.
+    Top,
+}
+
+/// How the columns of [`OutputFormat::Table`] and [`OutputFormat::Top`] are
+/// laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Orientation {
+    /// One column per field under a header, one line per row
+    #[default]
+    Horizontal,
+    /// One line per field with its name and value, indented under its
+    /// groups: a block of lines per row, or a screen redrawn in place with
+    /// [`OutputFormat::Top`]. Suits metric sets wider than the terminal.
+    Vertical,
 }
 
-enum SnapshotterKind {
-    Snapshotter(Snapshotter),
-    PerThread,
+pub struct CliRegister {
+    registry: Arc<Registry>,
+    /// Generation of the registry when the columns were last computed
+    generation: Option<u64>,
+    /// Position and statistic of the fields displaying each metric of the
+    /// registry, in registration order
+    columns: Vec<Vec<(usize, Option<Stat>)>>,
+    /// Buffers reused by each row
+    values: Vec<Value>,
+    samples: Vec<f64>,
+    start: Instant,
+    format: OutputFormat,
+    orientation: Orientation,
+    table: Table,
+    /// Whether the table layout is recomputed from the metrics by header()
+    auto_layout: bool,
+    quantiles: Vec<f64>,
+    number_format: NumberFormat,
+    color: ColorChoice,
+    bar: Bar,
+    grouping: Grouping,
+    filter: Filter,
+    /// Patterns of the metrics displayed first, in order
+    column_order: Vec<Pattern>,
+    /// Display kind of the counters and gauges matching each pattern
+    display_kinds: Vec<(Pattern, DisplayKind)>,
+    /// Whether a line naming added and removed columns precedes a new header
+    change_marker: bool,
+    /// Whether the header ends with the unit of each column
+    unit_row: bool,
+    /// Width of the panels the table is split into, the terminal width if None
+    max_width: Option<usize>,
+    /// Whether the previous line was split into panels
+    split: bool,
+    /// Layout of the last header, if the layout changed since without a
+    /// header being printed, e.g. by [`CliRegister::row`]
+    header_layout: Option<Table>,
+    /// Last printed header, printed again when a new layout changes it
+    last_header: Option<String>,
+    /// Whether the header starts with the description of each column
+    legend: bool,
+    interval: Duration,
+    final_row: bool,
+    output: Box<dyn Write + Send>,
+    /// Whether the output is known to be a terminal, for [`ColorChoice::Auto`]
+    output_is_terminal: bool,
+    /// Whether the alternate screen used by [`OutputFormat::Top`] was entered
+    screen: bool,
 }
 
 impl CliRegister {
+    /// Install a [`CliRecorder`] as the global recorder. Use [`CliRecorder`]
+    /// or [`CliLayer`] instead to display the table along with another exporter.
     pub fn install() -> Result<Self, SetRecorderError> {
-        let recorder = DebuggingRecorder::new();
-        let snapshotter = recorder.snapshotter();
-        recorder.install()?;
-        Ok(Self {
-            snapshotter: SnapshotterKind::Snapshotter(snapshotter),
-            table: TableBuilder::new().build(),
-        })
+        let recorder = CliRecorder::new();
+        let register = recorder.register();
+        metrics::set_boxed_recorder(Box::new(recorder))?;
+        Ok(register)
     }
 
+    /// Install a recorder which only collects metrics of the current thread.
+    /// Metrics recorded by other threads are discarded, but the register
+    /// itself can be moved to another thread, e.g. with [`CliRegister::spawn`].
     pub fn install_on_thread() -> Self {
-        let recorder = DebuggingRecorder::per_thread();
-        _ = recorder.install();
+        let recorder = CliRecorder::per_thread();
+        let register = recorder.register();
+        _ = metrics::set_boxed_recorder(Box::new(recorder));
+        register
+    }
+
+    pub(crate) fn new(registry: Arc<Registry>) -> Self {
         Self {
-            snapshotter: SnapshotterKind::PerThread,
+            registry,
+            generation: None,
+            columns: Vec::new(),
+            values: Vec::new(),
+            samples: Vec::new(),
+            start: Instant::now(),
+            format: OutputFormat::Table,
+            orientation: Orientation::Horizontal,
             table: TableBuilder::new().build(),
+            auto_layout: true,
+            quantiles: DEFAULT_QUANTILES.to_vec(),
+            number_format: NumberFormat::default(),
+            color: ColorChoice::default(),
+            bar: Bar::default(),
+            grouping: Grouping::default(),
+            filter: Filter::default(),
+            column_order: Vec::new(),
+            display_kinds: Vec::new(),
+            change_marker: false,
+            unit_row: false,
+            max_width: None,
+            split: false,
+            header_layout: None,
+            last_header: None,
+            legend: false,
+            interval: Duration::from_secs(1),
+            final_row: false,
+            output: Box::new(io::stdout()),
+            output_is_terminal: io::stdout().is_terminal(),
+            screen: false,
         }
     }
 
-    fn snapshot(&self) -> Snapshot {
-        match &self.snapshotter {
-            SnapshotterKind::Snapshotter(snapshotter) => snapshotter.snapshot(),
-            SnapshotterKind::PerThread => {
-                Snapshotter::current_thread_snapshot().expect("No current thread snapshot")
-            }
-        }
+    /// Set how the table is written. Defaults to [`OutputFormat::Table`].
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set how the fields of the table and full-screen formats are laid out.
+    /// Defaults to [`Orientation::Horizontal`].
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Write the table to the given output instead of stdout, e.g.
+    /// `io::stderr()` or a file. Only `io::stdout()` and `io::stderr()` are
+    /// detected as terminals, for [`ColorChoice::Auto`] and the width of
+    /// [`CliRegister::with_max_width`]; see
+    /// [`CliRegister::with_output_terminal`] for other outputs.
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        let any: &dyn Any = &output;
+        self.output_is_terminal = if let Some(stderr) = any.downcast_ref::<io::Stderr>() {
+            stderr.is_terminal()
+        } else if let Some(stdout) = any.downcast_ref::<io::Stdout>() {
+            stdout.is_terminal()
+        } else {
+            false
+        };
+        self.output = Box::new(output);
+        self.configure_table();
+        self
+    }
+
+    /// Set whether the output is a terminal, when it isn't detected by
+    /// [`CliRegister::with_output`], e.g. for a locked handle or `/dev/tty`.
+    pub fn with_output_terminal(mut self, terminal: bool) -> Self {
+        self.output_is_terminal = terminal;
+        self.configure_table();
+        self
+    }
+
+    /// Set the time between two lines printed by [`CliRegister::print_loop`]
+    /// and [`CliRegister::spawn`]. Defaults to one second.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Print a last line when the loop started by [`CliRegister::spawn`] is
+    /// stopped, so that the metrics recorded since the previous line are not lost.
+    pub fn with_final_row(mut self, final_row: bool) -> Self {
+        self.final_row = final_row;
+        self
+    }
+
+    /// Set the quantiles displayed for histograms, e.g. `&[0.5, 0.99]` for
+    /// `p50` and `p99` columns. Defaults to [`DEFAULT_QUANTILES`].
+    pub fn with_quantiles(mut self, quantiles: &[f64]) -> Self {
+        self.quantiles = quantiles.to_vec();
+        self
+    }
+
+    /// Set how values are formatted according to their unit, e.g. with
+    /// `NumberFormat::new().with_precision(1)`. Units are set with the
+    /// `describe_*` macros, or with [`TableBuilder::with_unit`] for an
+    /// explicit layout. CSV and JSON output are never formatted.
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self.configure_table();
+        self
+    }
+
+    /// Choose whether the color rules are applied, see [`ColorRules`].
+    /// Defaults to [`ColorChoice::Auto`]: only when writing to a terminal and
+    /// the `NO_COLOR` environment variable is not set.
+    pub fn with_color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self.configure_table();
+        self
+    }
+
+    /// Set how metrics with the `view=histogram` label are drawn, e.g.
+    /// `Bar::new().with_width(20).with_value(true)`. Use [`TableBuilder::with_bar`]
+    /// for an explicit layout.
+    pub fn with_bar(mut self, bar: Bar) -> Self {
+        self.bar = bar;
+        self
+    }
+
+    /// Set how metrics are grouped into columns. By default key names are
+    /// split on dots and all labels become grouping levels, sorted by key, so
+    /// that `http.requests{method=GET}` and `http.requests{method=POST}` are
+    /// displayed as columns `GET` and `POST` of the group `requests`, itself
+    /// part of the group `http`.
+    pub fn with_grouping(mut self, grouping: Grouping) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    /// Choose which labels become grouping levels, in the given order, like
+    /// [`Grouping::with_labels`]. Labels not listed here are folded into the
+    /// column name, e.g. `GET{status=200}` when grouping by `method` only.
+    pub fn with_grouping_labels(mut self, labels: &[&str]) -> Self {
+        self.grouping = std::mem::take(&mut self.grouping).with_labels(labels);
+        self
+    }
+
+    /// Only display the metrics selected by `filter`, e.g.
+    /// `Filter::new().include(Pattern::glob("http.*"))`, or
+    /// `Filter::from_env()?` to select them at launch with the
+    /// `METRICS_CLI_FILTER` environment variable.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Display the metrics matching the first pattern first, then the ones
+    /// matching the second one, and so on. Metrics are otherwise sorted by
+    /// path, and a group is split when patterns select only some of its metrics.
+    pub fn with_column_order(mut self, patterns: Vec<Pattern>) -> Self {
+        self.column_order = patterns;
+        self
+    }
+
+    /// Display the counters and gauges matching `pattern` as `display_kind`,
+    /// e.g. [`DisplayKind::Rate`] for counters without a per-second unit.
+    /// When several patterns match, the first one set wins.
+    pub fn with_display_kind(mut self, pattern: Pattern, display_kind: DisplayKind) -> Self {
+        self.display_kinds.push((pattern, display_kind));
+        self
+    }
+
+    /// When the table layout changes because metrics were added or removed,
+    /// precede the new header with a line naming the added and removed columns.
+    /// The layout of [`OutputFormat::Csv`] and [`OutputFormat::Tsv`] doesn't
+    /// change, so they have no such line.
+    pub fn with_change_marker(mut self, change_marker: bool) -> Self {
+        self.change_marker = change_marker;
+        self
+    }
+
+    /// Add a row with the declared unit of each column, e.g. `B` or `/s`,
+    /// under the column names of the table and full-screen headers. Values
+    /// are scaled from it, e.g. to `MiB`.
+    pub fn with_unit_row(mut self, unit_row: bool) -> Self {
+        self.unit_row = unit_row;
+        self
+    }
+
+    /// Split tables wider than `max_width` columns into panels stacked on top
+    /// of each other, each with its own header, preferably between groups.
+    /// Defaults to the width of the terminal when writing to one, and no
+    /// limit otherwise. Only applies to [`OutputFormat::Table`] and
+    /// [`OutputFormat::Top`] with the horizontal orientation.
+    pub fn with_max_width(mut self, max_width: usize) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    /// Start the table and full-screen headers with a legend: one line with
+    /// the description of each described column, e.g. `# latency: Time to
+    /// answer a request`. Descriptions are set with the `describe_*` macros,
+    /// or with [`TableBuilder::with_description`] for an explicit layout.
+    pub fn with_legend(mut self, legend: bool) -> Self {
+        self.legend = legend;
+        self
+    }
+
+    /// Apply the settings of a configuration file or environment variable,
+    /// see [`Config`].
+    pub fn with_config(self, config: &Config) -> Result<Self, ConfigError> {
+        config.apply(self)
+    }
+
+    /// Display the given table instead of building one automatically from
+    /// the recorded metrics. Only metrics bound to a field with
+    /// [`TableBuilder::metric`] or [`TableBuilder::histogram`] are displayed,
+    /// and the header never changes.
+    pub fn with_layout(mut self, layout: TableBuilder) -> Self {
+        self.table = layout.build();
+        self.configure_table();
+        self.auto_layout = false;
+        // The columns must be recomputed for the new table
+        self.generation = None;
+        self
     }
 
     pub fn header(&mut self) -> String {
-        // Recompute table header
-        self.table = table_from_snapshot(self.snapshot());
-        self.table.header()
+        _ = self.update_layout();
+        self.header_layout = None;
+        let header = self.render_header();
+        self.last_header = Some(header.clone());
+        header
     }
 
-    pub fn status(&mut self) -> String {
-        let snapshot = self.snapshot();
-        let mut items: Vec<(Option<usize>, DebugValue)> = snapshot
-            .into_vec()
-            .into_iter()
-            .map(|item| {
-                let path = item
-                    .0
-                    .key()
-                    .name()
-                    .to_string()
-                    .split('.')
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>();
-                let i = self.table.position_of(path.clone());
-                (i, item.3)
-            })
+    fn render_header(&mut self) -> String {
+        match self.format {
+            // Each line of a split table has the headers of its panels
+            OutputFormat::Table if self.panels().len() > 1 => self.legend_lines().join("\n"),
+            OutputFormat::Table => self.header_lines().join("\n"),
+            OutputFormat::Csv => self.table.csv_header(','),
+            OutputFormat::Tsv => self.table.csv_header('\t'),
+            // Headers are part of each line
+            OutputFormat::JsonLines | OutputFormat::Top => String::new(),
+        }
+    }
+
+    /// Apply the formatting options to the table
+    fn configure_table(&mut self) {
+        self.table.set_number_format(self.number_format);
+        self.table
+            .set_colors(self.color.enabled(self.output_is_terminal));
+    }
+
+    /// Lines of the aligned header: the legend, the group and column names
+    /// and the units, as configured. The vertical layout only has the legend,
+    /// names and units being part of each row.
+    fn header_lines(&mut self) -> Vec<String> {
+        let mut lines = self.legend_lines();
+        if self.orientation == Orientation::Horizontal {
+            lines.extend(self.panel_header_lines(0..self.table.field_count()));
+        }
+        lines
+    }
+
+    /// Lines of the legend, if enabled.
+    fn legend_lines(&self) -> Vec<String> {
+        if !self.legend {
+            return Vec::new();
+        }
+        let legend = self.table.legend();
+        legend.iter().map(|line| format!("# {}", line)).collect()
+    }
+
+    /// Group and column names of the fields of a panel, followed by their
+    /// units if enabled and any field has one.
+    fn panel_header_lines(&mut self, panel: Range<usize>) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .table
+            .panel_header(panel.clone())
+            .lines()
+            .map(String::from)
             .collect();
-        items.sort_by_key(|x| x.0);
-        let values = items
-            .into_iter()
-            .map(|(_, value)| match value {
-                DebugValue::Counter(x) => Value::Int(x as i64),
-                DebugValue::Gauge(x) => Value::F64(*x),
-                DebugValue::Histogram(_) => todo!(),
-            })
+        if self.unit_row {
+            let units = self.table.panel_unit_row(panel);
+            if !units.is_empty() {
+                lines.push(units);
+            }
+        }
+        lines
+    }
+
+    /// Panels the fields are split into, see [`CliRegister::with_max_width`].
+    fn panels(&self) -> Vec<Range<usize>> {
+        let terminal_width = || {
+            self.output_is_terminal
+                .then(tui::terminal_size)
+                .flatten()
+                .map(|(width, _)| width)
+        };
+        match self.max_width.or_else(terminal_width) {
+            Some(max_width) if self.orientation == Orientation::Horizontal => {
+                self.table.panels(max_width)
+            }
+            _ => std::iter::once(0..self.table.field_count()).collect(),
+        }
+    }
+
+    /// Lines of each panel: its header, its row and, if `stats` is true, the
+    /// rows of [`Table::stats_rows`]. Panels are separated by an empty line.
+    fn panel_lines(&mut self, row: &Row, panels: &[Range<usize>], stats: bool) -> Vec<String> {
+        // Rows are rendered first, as fields may grow to fit their value
+        let rows: Vec<String> = panels
+            .iter()
+            .map(|panel| self.table.display_panel(&row.cells, panel.clone()))
             .collect();
-        self.table.display_row(values)
+        let mut lines = Vec::new();
+        for (i, (panel, row)) in panels.iter().zip(rows).enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            lines.extend(self.panel_header_lines(panel.clone()));
+            lines.push(row);
+            if stats {
+                lines.push(String::new());
+                let stats_rows = self.table.panel_stats_rows(panel.clone());
+                for (row, label) in stats_rows.into_iter().zip(["min", "max", "avg"]) {
+                    lines.push(format!("{}  {}", row, label));
+                }
+            }
+        }
+        lines
+    }
+
+    /// When metrics were registered or described since the previous call,
+    /// recompute the columns of each metric and, if the table is built
+    /// automatically, the table layout. Return the previous table if the
+    /// layout was recomputed.
+    fn update_layout(&mut self) -> Option<Table> {
+        let generation = self.registry.generation();
+        if self.generation == Some(generation) {
+            return None;
+        }
+        self.generation = Some(generation);
+        // CSV has a single header, so its columns are kept once there are some
+        let csv = matches!(self.format, OutputFormat::Csv | OutputFormat::Tsv);
+        let previous = if self.auto_layout && !(csv && self.table.field_count() > 0) {
+            let mut table = self.table_from_registry();
+            table.keep_state_of(&self.table);
+            let previous = std::mem::replace(&mut self.table, table);
+            self.configure_table();
+            Some(previous)
+        } else {
+            None
+        };
+        let (table, filter) = (&self.table, &self.filter);
+        self.columns = self.registry.with_metrics(|metrics| {
+            metrics
+                .iter()
+                .map(|metric| match filter.matches(metric.key()) {
+                    true => table.bindings_of(metric.key()).collect(),
+                    false => Vec::new(),
+                })
+                .collect()
+        });
+        previous
+    }
+
+    /// Build a table with the metrics of the registry selected by the filter,
+    /// grouped by path and sorted by column order, then path.
+    fn table_from_registry(&self) -> Table {
+        let registry = &self.registry;
+        let mut components: Vec<Component> = registry.with_metrics(|metrics| {
+            metrics
+                .iter()
+                .filter(|metric| self.filter.matches(metric.key()))
+                .map(|metric| Component {
+                    path: self.grouping.path(metric.key()),
+                    rank: self
+                        .column_order
+                        .iter()
+                        .position(|x| x.matches(metric.key()))
+                        .unwrap_or(self.column_order.len()),
+                    key: metric.key().clone(),
+                    kind: metric.kind(),
+                    display_kind: self
+                        .display_kinds
+                        .iter()
+                        .find(|(pattern, _)| pattern.matches(metric.key()))
+                        .map(|(_, display_kind)| display_kind.clone()),
+                    unit: registry.unit_of(metric.kind(), metric.key().name()),
+                    description: registry
+                        .description_of(metric.kind(), metric.key().name())
+                        .filter(|description| !description.is_empty()),
+                    labels: metric
+                        .key()
+                        .labels()
+                        .map(|label| (label.key().to_string(), label.value().to_string()))
+                        .collect(),
+                })
+                .collect()
+        });
+        components.sort_by(|a, b| (a.rank, &a.path).cmp(&(b.rank, &b.path)));
+        build(
+            TableBuilder::new(),
+            &mut components[..],
+            0,
+            &self.quantiles,
+            self.bar,
+        )
+        .build()
+    }
+
+    /// Fill `self.values` with the current value of each field.
+    fn sample(&mut self) {
+        self.values.clear();
+        self.values.resize(self.table.field_count(), Value::Empty);
+        let (values, samples, columns) = (&mut self.values, &mut self.samples, &self.columns);
+        self.registry.with_metrics(|metrics| {
+            // Metrics registered after the columns were computed are ignored
+            // until the next row
+            for (metric, columns) in metrics.iter().zip(columns) {
+                if metric.kind() == MetricKind::Histogram {
+                    // Drain the samples even if the histogram isn't displayed
+                    samples.clear();
+                    metric.drain_samples(samples);
+                    let summary = Summary::new(samples);
+                    for (i, stat) in columns {
+                        values[*i] = stat.map_or(Value::Empty, |stat| summary.value(stat));
+                    }
+                } else {
+                    let value = metric.value();
+                    for (i, stat) in columns {
+                        values[*i] = match stat {
+                            None => value.clone(),
+                            Some(_) => Value::Empty,
+                        };
+                    }
+                }
+            }
+        });
+    }
+
+    /// Return the next table line. When the table is built automatically and
+    /// metrics have been added or removed since the previous line, the line is
+    /// preceded by a new header.
+    pub fn status(&mut self) -> String {
+        self.status_at(Instant::now())
+    }
+
+    fn status_at(&mut self, now: Instant) -> String {
+        if self.format == OutputFormat::JsonLines {
+            let elapsed = now.saturating_duration_since(self.start);
+            return json::json_line(
+                &self.registry,
+                &self.quantiles,
+                &self.filter,
+                &mut self.samples,
+                SystemTime::now(),
+                elapsed,
+            );
+        }
+        let mut output = String::new();
+        let updated = self.update_layout();
+        let previous = self.header_layout.take().or(updated);
+        let panels = self.panels();
+        // A table which is no longer split needs its header again
+        let split = panels.len() > 1;
+        let joined = std::mem::replace(&mut self.split, split) && !split;
+        let row = self.sample_row(now);
+        // The row is rendered before the header, as fields may grow to fit
+        // their value
+        let line = match self.format {
+            OutputFormat::Table if self.orientation == Orientation::Vertical => {
+                // Blocks are separated by an empty line
+                let mut lines = self.table.vertical_lines(&row.cells);
+                lines.push(String::new());
+                lines.join("\n")
+            }
+            OutputFormat::Table if panels.len() > 1 => {
+                let mut lines = self.panel_lines(&row, &panels, false);
+                lines.push(String::new());
+                lines.join("\n")
+            }
+            OutputFormat::Table => self.table.display_cells(&row.cells),
+            OutputFormat::Csv => Table::csv_cells(&row.cells, ','),
+            OutputFormat::Tsv => Table::csv_cells(&row.cells, '\t'),
+            OutputFormat::Top => self.top_frame(&row, &panels),
+            OutputFormat::JsonLines => unreachable!("JSON lines are not built from the table"),
+        };
+        // The full-screen view redraws the header anyway. A new layout only
+        // needs a header if it differs, e.g. not for a filtered out metric.
+        if (previous.is_some() || joined) && self.format != OutputFormat::Top {
+            let header = self.render_header();
+            if joined || self.last_header.as_ref() != Some(&header) {
+                let added_or_removed = previous.filter(|previous| {
+                    previous.field_count() > 0 && previous.keys() != self.table.keys()
+                });
+                if let Some(previous) = added_or_removed.filter(|_| self.change_marker) {
+                    output.push_str(&change_marker(&previous, &self.table));
+                    output.push('\n');
+                }
+                if !header.is_empty() {
+                    output.push_str(&header);
+                    output.push('\n');
+                }
+                self.last_header = Some(header);
+            }
+        }
+        output.push_str(&line);
+        output
+    }
+
+    /// Return the next row without rendering it: the path, labels, value and
+    /// displayed value of each column, as [`CliRegister::status`] would
+    /// display them. Rows share the state of status lines, e.g. differences
+    /// are computed from the previous row or status line.
+    pub fn row(&mut self) -> Row {
+        // The next status line prints the header of the new layout
+        if let Some(previous) = self.update_layout() {
+            self.header_layout.get_or_insert(previous);
+        }
+        self.sample_row(Instant::now())
+    }
+
+    /// Sample the metrics and compute the next row of the current layout.
+    fn sample_row(&mut self, now: Instant) -> Row {
+        self.sample();
+        Row {
+            timestamp: SystemTime::now(),
+            elapsed: now.saturating_duration_since(self.start),
+            cells: self.table.row_at(self.values.iter().cloned(), now),
+        }
+    }
+
+    /// Redraw the screen with the header, the latest line and the statistics
+    /// of each column.
+    fn top_frame(&mut self, row: &Row, panels: &[Range<usize>]) -> String {
+        let mut lines = self.legend_lines();
+        if self.orientation == Orientation::Vertical {
+            lines.extend(self.table.vertical_lines(&row.cells));
+        } else {
+            lines.extend(self.panel_lines(row, panels, true));
+        }
+        let mut output = String::new();
+        if !self.screen {
+            output.push_str(tui::enter());
+            self.screen = true;
+        }
+        output.push_str(&tui::frame(&lines, tui::terminal_size()));
+        output
+    }
+
+    /// Write the output of [`CliRegister::header`] to the output.
+    pub fn print_header(&mut self) -> io::Result<()> {
+        let header = self.header();
+        self.print(&header)
+    }
+
+    /// Write the output of [`CliRegister::status`] to the output.
+    pub fn print_status(&mut self) -> io::Result<()> {
+        let status = self.status();
+        self.print(&status)
+    }
+
+    fn print(&mut self, lines: &str) -> io::Result<()> {
+        if lines.is_empty() {
+            // Nothing to display yet
+            return Ok(());
+        }
+        writeln!(self.output, "{}", lines)?;
+        self.output.flush()
     }
 
-    /// Start an infinite loop which prints a table line every second.
+    /// Start an infinite loop which prints a table line every interval (see
+    /// [`CliRegister::with_interval`]). It only returns if writing to the
+    /// output fails.
     ///
-    /// Note: you could write your own loop with a different interval, but be
-    /// aware that values with a unit type *PerSecond will just print the difference
-    /// from the last print invocation, indipendently of how much time has actually
-    /// passed.
-    pub fn print_loop(&mut self) -> ! {
-        println!("{}", self.header());
+    /// Note: you could write your own loop. Values with a unit type *PerSecond
+    /// are divided by the time actually elapsed since the previous line, so they
+    /// are correct at any interval.
+    pub fn print_loop(&mut self) -> io::Error {
+        if let Err(err) = self.print_header() {
+            return err;
+        }
         loop {
-            println!("{}", self.status());
-            std::thread::sleep(Duration::from_secs(1));
+            if let Err(err) = self.print_status() {
+                return err;
+            }
+            std::thread::sleep(self.interval);
         }
     }
+
+    /// Print the table from a background thread, a line every interval, until
+    /// the returned [`PrintLoop`] is stopped or dropped.
+    pub fn spawn(self) -> PrintLoop {
+        PrintLoop::spawn(self)
+    }
 }
 
-fn table_from_snapshot(snapshot: Snapshot) -> Table {
-    let mut components: Vec<Component> = snapshot
-        .into_vec()
-        .into_iter()
-        .map(|x| Component {
-            path: x
-                .0
-                .key()
-                .name()
-                .to_string()
-                .split(".")
-                .map(|x| x.to_string())
-                .collect(),
-            unit: x.1.unwrap_or(Unit::Count),
-            labels: x
-                .0
-                .key()
-                .labels()
-                .map(|label| (label.key().to_string(), label.value().to_string()))
-                .collect(),
-        })
-        .collect();
-    // TODO: remove clone
-    components.sort_by_key(|x| x.path.clone());
-    build(TableBuilder::new(), &mut components[..], 0).build()
+impl Drop for CliRegister {
+    fn drop(&mut self) {
+        if self.screen {
+            // Restore the terminal
+            _ = write!(self.output, "{}", tui::leave());
+            _ = self.output.flush();
+        }
+    }
+}
+
+/// Describe the columns added and removed between two table layouts
+fn change_marker(previous: &Table, table: &Table) -> String {
+    let mut changes = Vec::new();
+    let added = table.fields_missing_from(previous);
+    if !added.is_empty() {
+        changes.push(format!("added: {}", added.join(", ")));
+    }
+    let removed = previous.fields_missing_from(table);
+    if !removed.is_empty() {
+        changes.push(format!("removed: {}", removed.join(", ")));
+    }
+    format!("# columns {}", changes.join("; "))
 }
 
 struct Component {
     path: Vec<String>,
-    unit: Unit,
+    /// Position of the first column order pattern matching the key
+    rank: usize,
+    key: Key,
+    kind: MetricKind,
+    /// Display kind set with [`CliRegister::with_display_kind`]
+    display_kind: Option<DisplayKind>,
+    /// Unit set with `describe_*!`, undescribed metrics are not formatted
+    unit: Option<Unit>,
+    description: Option<SharedString>,
     labels: HashMap<String, String>,
 }
 
-fn build(mut builder: TableBuilder, components: &mut [Component], depth: usize) -> TableBuilder {
+fn build(
+    mut builder: TableBuilder,
+    components: &mut [Component],
+    depth: usize,
+    quantiles: &[f64],
+    bar: Bar,
+) -> TableBuilder {
     let mut i = 0;
     while i < components.len() {
         let name = components[i].path[depth].clone();
-        if components[i].path.len() == depth + 1 {
+        if components[i].path.len() == depth + 1 && components[i].kind == MetricKind::Histogram {
+            // a histogram is displayed as a group of statistics
+            let stats = histogram::stats(quantiles);
+            builder = builder.histogram(&name, components[i].key.clone(), &stats);
+            if let Some(unit) = components[i].unit {
+                builder = builder.with_unit(unit);
+            }
+            if let Some(rules) = ColorRules::from_labels(&components[i].labels) {
+                builder = builder.with_colors(rules);
+            }
+            if let Some(description) = &components[i].description {
+                builder = builder.with_description(description);
+            }
+            i += 1;
+        } else if components[i].path.len() == depth + 1 {
             let mut display_kind = match components[i].unit {
-                Unit::TerabitsPerSecond
-                | Unit::GigabitsPerSecond
-                | Unit::MegabitsPerSecond
-                | Unit::KilobitsPerSecond
-                | Unit::BitsPerSecond
-                | Unit::CountPerSecond => DisplayKind::Difference,
+                Some(
+                    Unit::TerabitsPerSecond
+                    | Unit::GigabitsPerSecond
+                    | Unit::MegabitsPerSecond
+                    | Unit::KilobitsPerSecond
+                    | Unit::BitsPerSecond
+                    | Unit::CountPerSecond,
+                ) => DisplayKind::Rate,
                 _ => DisplayKind::Number,
             };
-            if components[i].labels.get("view") == Some(&"histogram".to_string()) {
-                display_kind = DisplayKind::Histogram;
+            match components[i].labels.get("view").map(String::as_str) {
+                Some("histogram") => display_kind = DisplayKind::Histogram,
+                Some("sparkline") => display_kind = DisplayKind::Sparkline,
+                _ => {}
+            }
+            if let Some(kind) = &components[i].display_kind {
+                display_kind = kind.clone();
             }
-            builder = builder.field(&name, display_kind);
-            i = i + 1;
+            let is_bar = matches!(display_kind, DisplayKind::Histogram);
+            builder = builder.metric(&name, display_kind, components[i].key.clone());
+            if let Some(unit) = components[i].unit {
+                builder = builder.with_unit(unit);
+            }
+            if is_bar {
+                builder = builder.with_bar(bar);
+            }
+            if let Some(rules) = ColorRules::from_labels(&components[i].labels) {
+                builder = builder.with_colors(rules);
+            }
+            if let Some(description) = &components[i].description {
+                builder = builder.with_description(description);
+            }
+            i += 1;
         } else {
-            // make group, take out all items which share prefix
-            let group_size = components.iter().filter(|c| c.path[depth] == name).count();
+            // make group, take out all items which share prefix. A field with the
+            // same name as the group, e.g. a metric without labels, was sorted
+            // before and is not part of it.
+            let group_size = components[i..]
+                .iter()
+                .take_while(|c| c.path.len() > depth + 1 && c.path[depth] == name)
+                .count();
             builder = builder.group(&name, |group_builder| {
-                build(group_builder, &mut components[i..i + group_size], depth + 1)
+                build(
+                    group_builder,
+                    &mut components[i..i + group_size],
+                    depth + 1,
+                    quantiles,
+                    bar,
+                )
             });
-            i = i + group_size;
+            i += group_size;
         }
     }
     builder
@@ -162,10 +865,44 @@ fn build(mut builder: TableBuilder, components: &mut [Component], depth: usize)
 
 #[cfg(test)]
 mod tests {
-    use metrics::{counter, describe_counter, register_counter};
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{counter, describe_counter, histogram, register_counter};
 
     use super::*;
 
+    /// In-memory output which can be inspected while the register owns it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct FailingOutput;
+
+    impl Write for FailingOutput {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
     fn simple_header() {
         unsafe {
@@ -197,12 +934,12 @@ mod tests {
         let mut register = CliRegister::install_on_thread();
         counter!("val_a", 10);
         counter!("val_b", 20);
-        _ = register.header(); // TODO: this easy to misuse
+        _ = register.header();
         assert_eq!(register.status(), ["   10    20"].join("\n"));
     }
 
     #[test]
-    fn simple_difference() {
+    fn simple_rate() {
         unsafe {
             metrics::clear_recorder();
         }
@@ -210,10 +947,15 @@ mod tests {
         register_counter!("val_a");
         describe_counter!("val_a", Unit::CountPerSecond, "Val A");
         counter!("val_a", 10);
-        _ = register.header(); // TODO: this easy to misuse
-        assert_eq!(register.status(), ["   10"].join("\n"));
+        _ = register.header();
+        let start = Instant::now();
+        assert_eq!(register.status_at(start), ["    -"].join("\n"));
+        counter!("val_a", 22);
+        let now = start + Duration::from_secs(1);
+        assert_eq!(register.status_at(now), ["   22"].join("\n"));
         counter!("val_a", 22);
-        assert_eq!(register.status(), ["   22"].join("\n"));
+        let now = now + Duration::from_millis(250);
+        assert_eq!(register.status_at(now), ["   88"].join("\n"));
     }
 
     #[test]
@@ -226,7 +968,569 @@ mod tests {
         register_counter!("a.val_a");
         counter!("val_b", 20);
         counter!("a.val_a", 10);
-        _ = register.header(); // TODO: this easy to misuse
+        _ = register.header();
         assert_eq!(register.status(), ["   10      20"].join("\n"));
     }
+
+    #[test]
+    fn histogram_status() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_quantiles(&[0.5]);
+        histogram!("latency", 1.0);
+        histogram!("latency", 3.0);
+        assert_eq!(
+            register.header(),
+            ["       latency", "count min max mean p50"].join("\n")
+        );
+        histogram!("latency", 2.0);
+        histogram!("latency", 6.0);
+        // the header doesn't consume the samples
+        assert_eq!(register.status(), ["    4   1   6    3   2"].join("\n"));
+        assert_eq!(register.status(), ["    0   -   -    -   -"].join("\n"));
+    }
+
+    #[test]
+    fn explicit_layout() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let layout = TableBuilder::new()
+            .group("requests", |group| {
+                group
+                    .metric(
+                        "get",
+                        DisplayKind::Number,
+                        ("requests", &[("method", "GET")]),
+                    )
+                    .metric(
+                        "post",
+                        DisplayKind::Number,
+                        ("requests", &[("method", "POST")]),
+                    )
+            })
+            .metric("errors", DisplayKind::Number, "errors");
+        let mut register = CliRegister::install_on_thread().with_layout(layout);
+        counter!("requests", 3, "method" => "POST");
+        counter!("unbound", 1);
+        assert_eq!(
+            register.header(),
+            ["requests |", "get post | errors"].join("\n")
+        );
+        assert_eq!(register.status(), ["  -    3        -"].join("\n"));
+        counter!("errors", 1);
+        counter!("requests", 2, "method" => "GET");
+        assert_eq!(register.status(), ["  2    3        1"].join("\n"));
+    }
+
+    #[test]
+    fn custom_output() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let output = SharedBuffer::default();
+        let mut register = CliRegister::install_on_thread().with_output(output.clone());
+        counter!("val_a", 10);
+        register.print_header().unwrap();
+        register.print_status().unwrap();
+        assert_eq!(output.contents(), "val_a\n   10\n");
+    }
+
+    #[test]
+    fn print_loop_thread() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let output = SharedBuffer::default();
+        let register = CliRegister::install_on_thread()
+            .with_output(output.clone())
+            .with_interval(Duration::from_millis(10))
+            .with_final_row(true);
+        counter!("val_a", 10);
+        let print_loop = register.spawn();
+        std::thread::sleep(Duration::from_millis(50));
+        counter!("val_a", 5);
+        let mut register = print_loop.stop().unwrap();
+        let contents = output.contents();
+        assert!(contents.starts_with("val_a\n   10\n"));
+        // The final row has the metrics recorded since the previous line
+        assert!(contents.ends_with("   15\n"));
+        assert_eq!(register.status(), "   15");
+
+        // Dropping the handle stops the loop, without final row by default
+        let output = SharedBuffer::default();
+        let register = register
+            .with_output(output.clone())
+            .with_interval(Duration::from_secs(3600))
+            .with_final_row(false);
+        drop(register.spawn());
+        assert_eq!(output.contents(), "val_a\n   15\n");
+    }
+
+    #[test]
+    fn output_error() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_output(FailingOutput);
+        counter!("val_a", 10);
+        let err = register.print_header().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        let err = register.print_loop();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn automatic_header() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread();
+        assert_eq!(register.status(), "");
+        counter!("val_a", 10);
+        assert_eq!(register.status(), ["val_a", "   10"].join("\n"));
+        assert_eq!(register.status(), ["   10"].join("\n"));
+        counter!("val_b", 20);
+        assert_eq!(register.status(), ["val_a val_b", "   10    20"].join("\n"));
+    }
+
+    #[test]
+    fn change_marker() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_change_marker(true);
+        counter!("val_a", 10);
+        assert_eq!(register.status(), ["val_a", "   10"].join("\n"));
+        counter!("g1.val_b", 20);
+        assert_eq!(
+            register.status(),
+            [
+                "# columns added: g1.val_b",
+                " g1   |",
+                "val_b | val_a",
+                "   20      10"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn rate_survives_new_header() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread();
+        register_counter!("val_a");
+        describe_counter!("val_a", Unit::CountPerSecond, "Val A");
+        counter!("val_a", 10);
+        let start = Instant::now();
+        assert_eq!(register.status_at(start), ["val_a", "    -"].join("\n"));
+        counter!("val_a", 5);
+        counter!("val_b", 1);
+        let now = start + Duration::from_secs(1);
+        assert_eq!(
+            register.status_at(now),
+            ["val_a val_b", "    5     1"].join("\n")
+        );
+    }
+
+    #[test]
+    fn labeled_columns() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread();
+        counter!("requests", 1);
+        counter!("requests", 2, "method" => "GET");
+        counter!("requests", 3, "method" => "POST");
+        assert_eq!(
+            register.status(),
+            [
+                "         | requests",
+                "requests | GET POST",
+                "       1     2    3"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn folded_labels() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_grouping_labels(&["method"]);
+        counter!("requests", 1, "status" => "200", "method" => "GET");
+        counter!("requests", 2, "method" => "GET", "status" => "500");
+        assert_eq!(
+            register.status(),
+            [
+                "           requests",
+                "GET{status=200} GET{status=500}",
+                "              1               2"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn custom_grouping() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread()
+            .with_grouping(Grouping::new().with_separators(&["::"]).with_max_depth(1));
+        counter!("http::server::requests", 1);
+        counter!("http::client::requests", 2);
+        assert_eq!(
+            register.status(),
+            [
+                "              http",
+                "client::requests server::requests",
+                "               2                1"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn filtered_metrics() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread()
+            .with_filter(Filter::parse("http.*, !method=POST").unwrap());
+        counter!("http.requests", 1, "method" => "GET");
+        counter!("http.requests", 2, "method" => "POST");
+        counter!("db.queries", 3);
+        assert_eq!(
+            register.status(),
+            ["  http", "requests", "     GET", "       1"].join("\n")
+        );
+    }
+
+    #[test]
+    fn configured_layout() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let config = Config::from_toml(
+            r#"
+            order = "db.*"
+            [kinds]
+            "http.*" = "difference"
+            "#,
+        )
+        .unwrap();
+        let mut register = CliRegister::install_on_thread()
+            .with_config(&config)
+            .unwrap();
+        counter!("http.requests", 5);
+        counter!("db.queries", 3);
+        assert_eq!(
+            register.status(),
+            [
+                "  db    |   http",
+                "queries | requests",
+                "      3          5"
+            ]
+            .join("\n")
+        );
+        counter!("http.requests", 2);
+        assert!(register.status().ends_with("      3          2"));
+    }
+
+    #[test]
+    fn stable_header() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register =
+            CliRegister::install_on_thread().with_filter(Filter::parse("http.*").unwrap());
+        metrics::gauge!("http.req", 1234567.0);
+        assert_eq!(
+            register.status(),
+            ["  http", "     req", " 1234567"].join("\n")
+        );
+        metrics::gauge!("http.req", 1.0);
+        assert_eq!(register.status(), "       1");
+        // The layout is recomputed, but neither the widths nor the header change
+        counter!("db.other", 1);
+        assert_eq!(register.status(), "       1");
+    }
+
+    #[test]
+    fn structured_row() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread()
+            .with_quantiles(&[])
+            .with_display_kind(Pattern::glob("requests"), DisplayKind::Difference);
+        counter!("requests", 5, "method" => "GET");
+        histogram!("latency", 2.0);
+        let row = register.row();
+        assert_eq!(row.cells.len(), 5);
+        counter!("requests", 3, "method" => "GET");
+        let row = register.row();
+        let cell = row.cell(&["requests", "GET"]).unwrap();
+        assert_eq!(*cell.labels, [("method".to_string(), "GET".to_string())]);
+        assert_eq!(cell.display_kind, DisplayKind::Difference);
+        assert_eq!(
+            (&cell.value, &cell.display_value),
+            (&Value::Int(8), &Value::Int(3))
+        );
+        let cell = row.cell(&["latency", "count"]).unwrap();
+        assert_eq!(cell.stat, Some(Stat::Count));
+        assert_eq!(cell.value, Value::Int(0));
+        // The text is rendered from the same cells, under the header of the
+        // layout computed by row()
+        assert_eq!(
+            register.status(),
+            [
+                "     latency       | requests",
+                "count min max mean |      GET",
+                "    0   -   -    -          0"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn vertical_output() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_orientation(Orientation::Vertical);
+        counter!("http.requests", 3);
+        counter!("errors", 1);
+        assert_eq!(register.header(), "");
+        assert_eq!(
+            register.status(),
+            ["errors      1", "http", "  requests  3", ""].join("\n")
+        );
+    }
+
+    #[test]
+    fn split_table() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_max_width(16);
+        counter!("http.requests", 3);
+        counter!("http.errors", 1);
+        counter!("db.queries", 12);
+        assert_eq!(register.header(), "");
+        assert_eq!(
+            register.status(),
+            [
+                "  db",
+                "queries",
+                "     12",
+                "",
+                "     http",
+                "errors requests",
+                "     1        3",
+                ""
+            ]
+            .join("\n")
+        );
+        // The header is printed again once the table fits
+        register = register.with_max_width(80);
+        assert_eq!(
+            register.status(),
+            [
+                "  db    |      http",
+                "queries | errors requests",
+                "     12        1        3"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn unit_format() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread()
+            .with_quantiles(&[])
+            .with_number_format(NumberFormat::new().with_precision(1));
+        describe_counter!("sent", Unit::Bytes, "Sent bytes");
+        metrics::describe_histogram!("latency", Unit::Seconds, "Latency");
+        counter!("sent", 3 << 20);
+        // Undescribed metrics are not formatted
+        counter!("total", 1234567);
+        histogram!("latency", 0.0012);
+        histogram!("latency", 0.0031);
+        assert_eq!(
+            register.status(),
+            [
+                "         latency           |      |",
+                "count    min    max   mean | sent |    total",
+                "    2  1.2ms  3.1ms  2.1ms   3MiB  1234567"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn legend_and_units() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread()
+            .with_quantiles(&[])
+            .with_legend(true)
+            .with_unit_row(true);
+        describe_counter!("sent", Unit::Bytes, "Sent bytes");
+        metrics::describe_histogram!("latency", Unit::Milliseconds, "Request latency");
+        counter!("sent", 3);
+        counter!("errors", 1);
+        histogram!("latency", 2.0);
+        assert_eq!(
+            register.header(),
+            [
+                "# latency: Request latency",
+                "# sent: Sent bytes",
+                "       |      latency       |",
+                "errors | count min max mean | sent",
+                "                ms  ms   ms      B"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn late_description() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread()
+            .with_legend(true)
+            .with_unit_row(true);
+        register_counter!("sent");
+        counter!("sent", 3 << 20);
+        // No empty unit row without units
+        assert_eq!(register.status(), ["    sent", " 3145728"].join("\n"));
+        // A description changes the header
+        describe_counter!("sent", Unit::Bytes, "Sent bytes");
+        assert_eq!(
+            register.status(),
+            ["# sent: Sent bytes", "    sent", "       B", "    3MiB"].join("\n")
+        );
+    }
+
+    #[test]
+    fn colored_cells() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_color(ColorChoice::Always);
+        metrics::gauge!("load", 0.9, "warning" => "0.8", "critical" => "0.95");
+        metrics::gauge!("idle", 0.0, "color" => "dim-zero");
+        assert_eq!(
+            register.status(),
+            ["idle load", "   \x1b[2m0\x1b[0m  \x1b[33m0.9\x1b[0m"].join("\n")
+        );
+        let mut register = register.with_color(ColorChoice::Never);
+        assert_eq!(register.status(), "   0  0.9");
+        let mut register = register
+            .with_color(ColorChoice::Auto)
+            .with_output(SharedBuffer::default());
+        assert_eq!(register.status(), "   0  0.9");
+        let mut register = register.with_output_terminal(true);
+        if std::env::var_os("NO_COLOR").is_none_or(|x| x.is_empty()) {
+            assert_eq!(register.status(), "   \x1b[2m0\x1b[0m  \x1b[33m0.9\x1b[0m");
+        }
+    }
+
+    #[test]
+    fn csv_output() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread().with_format(OutputFormat::Csv);
+        counter!("g1.val_a", 10);
+        counter!("requests", 2, "method" => "GET,POST");
+        histogram!("latency", 2.0);
+        assert_eq!(
+            register.status(),
+            [
+                "g1.val_a,latency.count,latency.min,latency.max,latency.mean,latency.p50,latency.p90,latency.p99,\"requests.GET,POST\"",
+                "10,1,2,2,2,2,2,2,2"
+            ]
+            .join("\n")
+        );
+        assert_eq!(register.status(), "10,0,,,,,,,2");
+        // The header isn't repeated for new metrics
+        counter!("errors", 1);
+        assert_eq!(register.status(), "10,0,,,,,,,2");
+    }
+
+    #[test]
+    fn json_output() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let mut register = CliRegister::install_on_thread()
+            .with_format(OutputFormat::JsonLines)
+            .with_quantiles(&[0.5]);
+        counter!("val_a", 10);
+        counter!("requests", 2, "method" => "GET");
+        metrics::gauge!("load", 0.5);
+        histogram!("latency", 2.0);
+        histogram!("latency", 4.0);
+        assert_eq!(register.header(), "");
+        let line: serde_json::Value = serde_json::from_str(&register.status()).unwrap();
+        assert!(line["timestamp"].is_u64());
+        assert!(line["elapsed"].is_f64());
+        assert_eq!(
+            line["metrics"],
+            serde_json::json!({
+                "val_a": 10,
+                "requests{method=GET}": 2,
+                "load": 0.5,
+                "latency": {"count": 2, "min": 2.0, "max": 4.0, "mean": 3.0, "p50": 2.0},
+            })
+        );
+        let line: serde_json::Value = serde_json::from_str(&register.status()).unwrap();
+        assert_eq!(
+            line["metrics"]["latency"],
+            serde_json::json!({"count": 0, "min": null, "max": null, "mean": null, "p50": null})
+        );
+    }
+
+    #[test]
+    fn top_output() {
+        unsafe {
+            metrics::clear_recorder();
+        }
+        let output = SharedBuffer::default();
+        let mut register = CliRegister::install_on_thread()
+            .with_format(OutputFormat::Top)
+            .with_output(output.clone());
+        counter!("val_a", 10);
+        register.print_status().unwrap();
+        counter!("val_a", 20);
+        let frame = register.status();
+        assert!(!frame.contains("\x1b[?1049h"));
+        assert!(frame.ends_with(
+            &[
+                "val_a\x1b[K",
+                "   30\x1b[K",
+                "\x1b[K",
+                "   10  min\x1b[K",
+                "   30  max\x1b[K",
+                "   20  avg\x1b[J"
+            ]
+            .join("\n")
+        ));
+        drop(register);
+        let contents = output.contents();
+        assert!(contents.starts_with("\x1b[?1049h"));
+        assert!(contents.ends_with("\x1b[?1049l"));
+    }
 }
@@ -0,0 +1,64 @@
+use std::{
+    io,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+};
+
+use crate::CliRegister;
+
+/// Handle of the background thread started by [`CliRegister::spawn`].
+///
+/// Dropping the handle stops the thread and waits for it to terminate.
+pub struct PrintLoop {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<io::Result<CliRegister>>>,
+}
+
+impl PrintLoop {
+    pub(crate) fn spawn(mut register: CliRegister) -> Self {
+        let (stop, stop_receiver) = mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            register.print_header()?;
+            register.print_status()?;
+            // Dropping the sender disconnects the channel and ends the loop
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(register.interval)
+            {
+                register.print_status()?;
+            }
+            if register.final_row {
+                register.print_status()?;
+            }
+            Ok(register)
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop the loop, waiting for the last line to be printed, and give back
+    /// the register. Fails if writing to the output failed, in which case the
+    /// loop had already terminated.
+    pub fn stop(mut self) -> io::Result<CliRegister> {
+        match self.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    fn join(&mut self) -> std::thread::Result<io::Result<CliRegister>> {
+        self.stop.take();
+        self.thread
+            .take()
+            .expect("print loop already joined")
+            .join()
+    }
+}
+
+impl Drop for PrintLoop {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            _ = self.join();
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Recorder,
+    SharedString, Unit,
+};
+use metrics_util::{layers::Layer, AtomicBucket, MetricKind};
+
+use crate::{CliRegister, Value};
+
+/// A [`Recorder`] storing the metrics displayed by a [`CliRegister`].
+///
+/// Counters and gauges are stored in atomics and histogram samples in
+/// lock-free buckets. Recording through a registered handle, e.g. one
+/// returned by `register_counter!`, only updates them. Macros such as
+/// `counter!` also look the metric up by key under a read lock, without
+/// allocating, which waits while another thread registers a new metric.
+///
+/// [`CliRegister::install`] installs a recorder as the global one. To also
+/// send the metrics to another exporter, combine it with other recorders
+/// instead, e.g. with [`metrics_util::layers::FanoutBuilder`], or use
+/// [`CliLayer`]:
+///
+/// ```
+/// use metrics_exporter_cli::CliRecorder;
+/// use metrics_util::layers::FanoutBuilder;
+///
+/// let recorder = CliRecorder::new();
+/// let register = recorder.register();
+/// let fanout = FanoutBuilder::default().add_recorder(recorder).build();
+/// metrics::set_boxed_recorder(Box::new(fanout)).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct CliRecorder {
+    storage: Storage,
+}
+
+#[derive(Clone)]
+enum Storage {
+    Shared(Arc<Registry>),
+    /// Each thread records its metrics in THREAD_REGISTRY
+    PerThread,
+}
+
+thread_local! {
+    static THREAD_REGISTRY: RefCell<Option<Arc<Registry>>> = const { RefCell::new(None) };
+}
+
+impl CliRecorder {
+    pub fn new() -> Self {
+        Self {
+            storage: Storage::Shared(Arc::default()),
+        }
+    }
+
+    /// Create a recorder which stores the metrics of each thread separately,
+    /// with a new registry for the current thread. Metrics recorded by
+    /// threads without a registry are discarded.
+    pub(crate) fn per_thread() -> Self {
+        THREAD_REGISTRY.with(|cell| *cell.borrow_mut() = Some(Arc::default()));
+        Self {
+            storage: Storage::PerThread,
+        }
+    }
+
+    /// Create a register displaying the metrics recorded by this recorder.
+    pub fn register(&self) -> CliRegister {
+        CliRegister::new(self.registry())
+    }
+
+    /// The registry storing the metrics. A per-thread recorder returns the
+    /// registry of the current thread, creating it if needed.
+    pub(crate) fn registry(&self) -> Arc<Registry> {
+        match &self.storage {
+            Storage::Shared(registry) => registry.clone(),
+            Storage::PerThread => THREAD_REGISTRY
+                .with(|cell| cell.borrow_mut().get_or_insert_with(Arc::default).clone()),
+        }
+    }
+
+    fn with_registry<T>(&self, f: impl FnOnce(&Registry) -> T) -> Option<T> {
+        match &self.storage {
+            Storage::Shared(registry) => Some(f(registry)),
+            Storage::PerThread => THREAD_REGISTRY.with(|cell| cell.borrow().as_deref().map(f)),
+        }
+    }
+}
+
+impl Recorder for CliRecorder {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.with_registry(|registry| {
+            registry.describe(MetricKind::Counter, key, unit, description)
+        });
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.with_registry(|registry| registry.describe(MetricKind::Gauge, key, unit, description));
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.with_registry(|registry| {
+            registry.describe(MetricKind::Histogram, key, unit, description)
+        });
+    }
+
+    fn register_counter(&self, key: &Key) -> Counter {
+        self.with_registry(
+            |registry| match registry.register(MetricKind::Counter, key) {
+                Handle::Counter(counter) => Counter::from_arc(counter),
+                _ => unreachable!(),
+            },
+        )
+        .unwrap_or_else(Counter::noop)
+    }
+
+    fn register_gauge(&self, key: &Key) -> Gauge {
+        self.with_registry(|registry| match registry.register(MetricKind::Gauge, key) {
+            Handle::Gauge(gauge) => Gauge::from_arc(gauge),
+            _ => unreachable!(),
+        })
+        .unwrap_or_else(Gauge::noop)
+    }
+
+    fn register_histogram(&self, key: &Key) -> Histogram {
+        self.with_registry(
+            |registry| match registry.register(MetricKind::Histogram, key) {
+                Handle::Histogram(histogram) => Histogram::from_arc(histogram),
+                _ => unreachable!(),
+            },
+        )
+        .unwrap_or_else(Histogram::noop)
+    }
+}
+
+impl Default for CliRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`Layer`] displaying the metrics in a [`CliRegister`] while forwarding
+/// them to the inner recorder, so that the table can be displayed along with
+/// another exporter.
+///
+/// ```
+/// use metrics_exporter_cli::CliLayer;
+/// use metrics_util::layers::Layer;
+/// # let exporter = metrics::NoopRecorder;
+///
+/// let layer = CliLayer::new();
+/// let register = layer.register();
+/// metrics::set_boxed_recorder(Box::new(layer.layer(exporter))).unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct CliLayer {
+    recorder: CliRecorder,
+}
+
+impl CliLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a register displaying the metrics recorded through the layer.
+    pub fn register(&self) -> CliRegister {
+        self.recorder.register()
+    }
+}
+
+impl<R> Layer<R> for CliLayer {
+    type Output = CliLayered<R>;
+
+    fn layer(&self, inner: R) -> Self::Output {
+        CliLayered {
+            recorder: self.recorder.clone(),
+            inner,
+        }
+    }
+}
+
+/// Recorder built by [`CliLayer`], recording each metric both in the
+/// [`CliRecorder`] and in the inner recorder.
+pub struct CliLayered<R> {
+    recorder: CliRecorder,
+    inner: R,
+}
+
+impl<R: Recorder> Recorder for CliLayered<R> {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.recorder
+            .describe_counter(key.clone(), unit, description.clone());
+        self.inner.describe_counter(key, unit, description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.recorder
+            .describe_gauge(key.clone(), unit, description.clone());
+        self.inner.describe_gauge(key, unit, description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.recorder
+            .describe_histogram(key.clone(), unit, description.clone());
+        self.inner.describe_histogram(key, unit, description);
+    }
+
+    fn register_counter(&self, key: &Key) -> Counter {
+        Counter::from_arc(Arc::new(Both(
+            self.recorder.register_counter(key),
+            self.inner.register_counter(key),
+        )))
+    }
+
+    fn register_gauge(&self, key: &Key) -> Gauge {
+        Gauge::from_arc(Arc::new(Both(
+            self.recorder.register_gauge(key),
+            self.inner.register_gauge(key),
+        )))
+    }
+
+    fn register_histogram(&self, key: &Key) -> Histogram {
+        Histogram::from_arc(Arc::new(Both(
+            self.recorder.register_histogram(key),
+            self.inner.register_histogram(key),
+        )))
+    }
+}
+
+/// Handle forwarding each operation to two handles.
+struct Both<T>(T, T);
+
+impl CounterFn for Both<Counter> {
+    fn increment(&self, value: u64) {
+        self.0.increment(value);
+        self.1.increment(value);
+    }
+
+    fn absolute(&self, value: u64) {
+        self.0.absolute(value);
+        self.1.absolute(value);
+    }
+}
+
+impl GaugeFn for Both<Gauge> {
+    fn increment(&self, value: f64) {
+        self.0.increment(value);
+        self.1.increment(value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.0.decrement(value);
+        self.1.decrement(value);
+    }
+
+    fn set(&self, value: f64) {
+        self.0.set(value);
+        self.1.set(value);
+    }
+}
+
+impl HistogramFn for Both<Histogram> {
+    fn record(&self, value: f64) {
+        self.0.record(value);
+        self.1.record(value);
+    }
+}
+
+/// Metrics stored by a [`CliRecorder`], in registration order.
+#[derive(Default)]
+pub(crate) struct Registry {
+    metrics: RwLock<Metrics>,
+    descriptions: RwLock<Descriptions>,
+    /// Incremented whenever a metric is registered or described
+    generation: AtomicU64,
+}
+
+type Descriptions = HashMap<(MetricKind, String), (Option<Unit>, SharedString)>;
+
+#[derive(Default)]
+struct Metrics {
+    list: Vec<Metric>,
+    /// Position in `list` of the counters, gauges and histograms, looked up
+    /// by key without cloning it
+    index: [HashMap<Key, usize>; 3],
+}
+
+impl Metrics {
+    fn get(&self, kind: MetricKind, key: &Key) -> Option<Handle> {
+        let i = self.index[index_of(kind)].get(key)?;
+        Some(self.list[*i].handle.clone())
+    }
+}
+
+/// Position of the index of the metrics of the given kind in `Metrics::index`
+fn index_of(kind: MetricKind) -> usize {
+    match kind {
+        MetricKind::Counter => 0,
+        MetricKind::Gauge => 1,
+        MetricKind::Histogram => 2,
+    }
+}
+
+impl Registry {
+    fn register(&self, kind: MetricKind, key: &Key) -> Handle {
+        let metrics = self.metrics.read().expect("metrics lock poisoned");
+        if let Some(handle) = metrics.get(kind, key) {
+            return handle;
+        }
+        drop(metrics);
+
+        let mut metrics = self.metrics.write().expect("metrics lock poisoned");
+        // Another thread could have registered the metric in the meantime
+        if let Some(handle) = metrics.get(kind, key) {
+            return handle;
+        }
+        let handle = match kind {
+            MetricKind::Counter => Handle::Counter(Arc::new(AtomicU64::new(0))),
+            MetricKind::Gauge => Handle::Gauge(Arc::new(AtomicU64::new(0f64.to_bits()))),
+            MetricKind::Histogram => Handle::Histogram(Arc::new(AtomicBucket::new())),
+        };
+        let i = metrics.list.len();
+        metrics.list.push(Metric {
+            key: key.clone(),
+            kind,
+            handle: handle.clone(),
+        });
+        metrics.index[index_of(kind)].insert(key.clone(), i);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        handle
+    }
+
+    fn describe(
+        &self,
+        kind: MetricKind,
+        key: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+    ) {
+        let mut descriptions = self
+            .descriptions
+            .write()
+            .expect("descriptions lock poisoned");
+        descriptions.insert((kind, key.as_str().to_string()), (unit, description));
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Number of changes to the registered metrics and their descriptions,
+    /// which can be compared to detect changes.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Call `f` with all the registered metrics, in registration order.
+    pub fn with_metrics<T>(&self, f: impl FnOnce(&[Metric]) -> T) -> T {
+        f(&self.metrics.read().expect("metrics lock poisoned").list)
+    }
+
+    /// Description of the metric with the given name, if it was described.
+    pub fn description_of(&self, kind: MetricKind, name: &str) -> Option<SharedString> {
+        let descriptions = self
+            .descriptions
+            .read()
+            .expect("descriptions lock poisoned");
+        descriptions
+            .get(&(kind, name.to_string()))
+            .map(|(_, description)| description.clone())
+    }
+
+    /// Unit of the metric with the given name, if it was described with one.
+    pub fn unit_of(&self, kind: MetricKind, name: &str) -> Option<Unit> {
+        let descriptions = self
+            .descriptions
+            .read()
+            .expect("descriptions lock poisoned");
+        descriptions
+            .get(&(kind, name.to_string()))
+            .and_then(|(unit, _)| *unit)
+    }
+}
+
+pub(crate) struct Metric {
+    key: Key,
+    kind: MetricKind,
+    handle: Handle,
+}
+
+#[derive(Clone)]
+enum Handle {
+    Counter(Arc<AtomicU64>),
+    /// Bits of the f64 value
+    Gauge(Arc<AtomicU64>),
+    Histogram(Arc<AtomicBucket<f64>>),
+}
+
+impl Metric {
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    pub fn kind(&self) -> MetricKind {
+        self.kind
+    }
+
+    /// Current value of a counter or gauge. Histograms have no single value,
+    /// see [`Metric::drain_samples`].
+    pub fn value(&self) -> Value {
+        match &self.handle {
+            Handle::Counter(counter) => Value::Int(counter.load(Ordering::Relaxed) as i64),
+            Handle::Gauge(gauge) => Value::F64(f64::from_bits(gauge.load(Ordering::Relaxed))),
+            Handle::Histogram(_) => Value::Empty,
+        }
+    }
+
+    /// Move the histogram samples recorded since the previous call to `samples`.
+    pub fn drain_samples(&self, samples: &mut Vec<f64>) {
+        if let Handle::Histogram(histogram) = &self.handle {
+            histogram.clear_with(|chunk| samples.extend_from_slice(chunk));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_once() {
+        let recorder = CliRecorder::new();
+        let registry = recorder.registry();
+        let key = Key::from_name("val_a");
+        recorder.register_counter(&key).increment(2);
+        recorder.register_counter(&key).increment(3);
+        recorder.register_gauge(&key).set(1.5);
+        assert_eq!(registry.generation(), 2);
+        let values: Vec<String> = registry
+            .with_metrics(|metrics| metrics.iter().map(|x| x.value().to_string()).collect());
+        assert_eq!(values, ["5", "1.5"]);
+    }
+
+    #[test]
+    fn histogram_samples() {
+        let recorder = CliRecorder::new();
+        let registry = recorder.registry();
+        let histogram = recorder.register_histogram(&Key::from_name("latency"));
+        histogram.record(1.0);
+        histogram.record(2.0);
+        let mut samples = Vec::new();
+        registry.with_metrics(|metrics| metrics[0].drain_samples(&mut samples));
+        assert_eq!(samples, [1.0, 2.0]);
+        samples.clear();
+        registry.with_metrics(|metrics| metrics[0].drain_samples(&mut samples));
+        assert!(samples.is_empty());
+    }
+
+    #[test]
+    fn layer() {
+        let inner = CliRecorder::new();
+        let layer = CliLayer::new();
+        let recorder = layer.layer(inner.clone());
+        recorder.describe_counter("val_a".into(), Some(Unit::Bytes), "Val A".into());
+        recorder
+            .register_counter(&Key::from_name("val_a"))
+            .increment(10);
+        for registry in [layer.recorder.registry(), inner.registry()] {
+            let values: Vec<String> = registry
+                .with_metrics(|metrics| metrics.iter().map(|x| x.value().to_string()).collect());
+            assert_eq!(values, ["10"]);
+            assert_eq!(
+                registry.unit_of(MetricKind::Counter, "val_a"),
+                Some(Unit::Bytes)
+            );
+        }
+    }
+
+    #[test]
+    fn description() {
+        let recorder = CliRecorder::new();
+        let registry = recorder.registry();
+        recorder.describe_counter("val_a".into(), Some(Unit::Bytes), "Val A".into());
+        assert_eq!(
+            registry.unit_of(MetricKind::Counter, "val_a"),
+            Some(Unit::Bytes)
+        );
+        assert_eq!(registry.unit_of(MetricKind::Gauge, "val_a"), None);
+        assert_eq!(registry.generation(), 1);
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use metrics::Unit;
+
+use crate::{DisplayKind, Stat, Value};
+
+/// A line of the table before it's rendered, returned by
+/// [`crate::CliRegister::row`] for programs consuming the samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    /// When the metrics were sampled
+    pub timestamp: SystemTime,
+    /// Time elapsed since the register was installed
+    pub elapsed: Duration,
+    /// One cell per column, in display order
+    pub cells: Vec<Cell>,
+}
+
+/// The value of a column in a [`Row`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    /// Groups of the column followed by its name, e.g. `["http", "requests", "GET"]`
+    pub path: Arc<[String]>,
+    /// Labels of the displayed metric, empty for columns without metric
+    pub labels: Arc<[(String, String)]>,
+    /// For histograms, the displayed statistic
+    pub stat: Option<Stat>,
+    pub unit: Option<Unit>,
+    pub display_kind: DisplayKind,
+    /// Value of the metric
+    pub value: Value,
+    /// Value derived from it according to the display kind, such as the
+    /// difference from the previous row
+    pub display_value: Value,
+}
+
+impl Row {
+    /// Cell of the column with the given path, e.g. `&["http", "requests"]`.
+    pub fn cell(&self, path: &[&str]) -> Option<&Cell> {
+        self.cells.iter().find(|cell| *cell.path == *path)
+    }
+}
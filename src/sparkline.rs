@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+
+use crate::Value;
+
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Number of values displayed by [`crate::DisplayKind::Sparkline`] fields
+/// when none is configured.
+pub(crate) const DEFAULT_LEN: usize = 10;
+
+/// The last values of a field, oldest first.
+#[derive(Clone, Debug)]
+pub(crate) struct History {
+    values: VecDeque<f64>,
+    len: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_LEN)
+    }
+}
+
+impl History {
+    pub fn new(len: usize) -> Self {
+        Self {
+            values: VecDeque::with_capacity(len + 1),
+            len,
+        }
+    }
+
+    /// Maximum number of values, which is also the width of the sparkline
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Add a value, forgetting the oldest one if the history is full.
+    /// Missing values are kept as gaps.
+    pub fn push(&mut self, value: &Value) {
+        self.values.push_back(match value {
+            Value::Int(x) => *x as f64,
+            Value::F64(x) => *x,
+            Value::Empty => f64::NAN,
+        });
+        if self.values.len() > self.len {
+            self.values.pop_front();
+        }
+    }
+
+    /// Draw the values scaled between their minimum and maximum, newest on
+    /// the right. Gaps are blank.
+    pub fn render(&self) -> String {
+        let known = self.values.iter().filter(|x| !x.is_nan());
+        let min = known.clone().copied().fold(f64::INFINITY, f64::min);
+        let max = known.copied().fold(f64::NEG_INFINITY, f64::max);
+        let mut output: String = std::iter::repeat_n(' ', self.len - self.values.len()).collect();
+        for x in &self.values {
+            output.push(if x.is_nan() {
+                ' '
+            } else if max > min {
+                let level = (x - min) / (max - min) * (LEVELS.len() - 1) as f64;
+                LEVELS[level.round() as usize]
+            } else {
+                LEVELS[0]
+            });
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_history() {
+        let mut history = History::new(4);
+        assert_eq!(history.render(), "    ");
+        for x in [1, 8, 4] {
+            history.push(&Value::Int(x));
+        }
+        assert_eq!(history.render(), " ▁█▄");
+        history.push(&Value::Empty);
+        history.push(&Value::Int(15));
+        assert_eq!(history.render(), "▄▁ █");
+        history.push(&Value::Int(15));
+        history.push(&Value::Int(15));
+        assert_eq!(history.render(), " ▁▁▁");
+    }
+}
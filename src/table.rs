@@ -1,6 +1,42 @@
-use std::fmt::Display;
+use std::{collections::HashSet, fmt::Display, ops::Range, sync::Arc, time::Instant};
 
-#[derive(Debug)]
+use metrics::{Key, Unit};
+use serde::Deserialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    bar::Bar,
+    color::{ColorRules, RESET},
+    histogram::Stat,
+    row::Cell,
+    sparkline::History,
+    tui,
+    units::NumberFormat,
+};
+
+/// Describe the layout of a [`Table`]: its groups and fields, in display order.
+///
+/// ```
+/// use metrics_exporter_cli::{DisplayKind, Stat, TableBuilder};
+///
+/// let table = TableBuilder::new()
+///     .group("http", |http| {
+///         http.metric("requests", DisplayKind::Difference, "http.requests")
+///             .histogram("latency", "http.latency", &[Stat::Mean, Stat::Quantile(0.99)])
+///     })
+///     .metric("errors", DisplayKind::Number, "errors")
+///     .build();
+/// assert_eq!(
+///     table.header(),
+///     [
+///         "       http         |",
+///         "         | latency  |",
+///         "requests | mean p99 | errors",
+///     ]
+///     .join("\n")
+/// );
+/// ```
+#[derive(Debug, Default)]
 pub struct TableBuilder {
     header: Vec<Entry>,
 }
@@ -10,6 +46,7 @@ impl TableBuilder {
         Self { header: Vec::new() }
     }
 
+    /// Add a group of entries, which are configured by `f`.
     pub fn group(
         mut self,
         name: &str,
@@ -22,54 +59,205 @@ impl TableBuilder {
         self
     }
 
-    pub fn field(mut self, name: &str, display_kind: DisplayKind) -> TableBuilder {
-        self.header.push(Entry::Field(Field {
+    /// Add a field which is not bound to any metric. Its values must be
+    /// supplied positionally to [`Table::display_row`].
+    pub fn field(self, name: &str, display_kind: DisplayKind) -> TableBuilder {
+        self.push_field(name, display_kind, None)
+    }
+
+    /// Add a field displaying the metric with the given key. Labels are part
+    /// of the key, so `Key::from_parts("requests", &[("method", "GET")])` and
+    /// `Key::from_parts("requests", &[("method", "POST")])` can be bound to
+    /// different fields.
+    pub fn metric(
+        self,
+        name: &str,
+        display_kind: DisplayKind,
+        key: impl Into<Key>,
+    ) -> TableBuilder {
+        let binding = Binding {
+            key: key.into(),
+            stat: None,
+        };
+        self.push_field(name, display_kind, Some(binding))
+    }
+
+    /// Add a group with one field for each of the given statistics of the
+    /// histogram with the given key.
+    pub fn histogram(self, name: &str, key: impl Into<Key>, stats: &[Stat]) -> TableBuilder {
+        let key = key.into();
+        self.group(name, |mut group| {
+            for stat in stats {
+                let binding = Binding {
+                    key: key.clone(),
+                    stat: Some(*stat),
+                };
+                group = group.push_field(&stat.name(), DisplayKind::Number, Some(binding));
+            }
+            group
+        })
+    }
+
+    /// Set the unit of the last added field, used to format its values (see
+    /// [`NumberFormat`]). When the last entry is a group, e.g. one added by
+    /// [`TableBuilder::histogram`], the unit applies to all its fields but the
+    /// histogram sample count.
+    pub fn with_unit(mut self, unit: Unit) -> TableBuilder {
+        fn set_unit(entry: &mut Entry, unit: Unit) {
+            match entry {
+                Entry::Field(field) => {
+                    let is_count = field
+                        .binding
+                        .as_ref()
+                        .is_some_and(|binding| binding.stat == Some(Stat::Count));
+                    if !is_count {
+                        field.display.unit = Some(unit);
+                    }
+                }
+                Entry::Group(group) => {
+                    for entry in group.entries.iter_mut() {
+                        set_unit(entry, unit);
+                    }
+                }
+            }
+        }
+        if let Some(entry) = self.header.last_mut() {
+            set_unit(entry, unit);
+        }
+        self
+    }
+
+    /// Set how the last added field draws its values, if it's a
+    /// [`DisplayKind::Histogram`] field.
+    pub fn with_bar(mut self, bar: Bar) -> TableBuilder {
+        if let Some(Entry::Field(field)) = self.header.last_mut() {
+            field.display.bar = bar;
+            if matches!(field.display.display_kind, DisplayKind::Histogram) {
+                field.display.len = width(&field.name).max(bar.width());
+            }
+        }
+        self
+    }
+
+    /// Set the rules coloring the values of the last added entry. When the
+    /// last entry is a group, the rules apply to all its fields. Colors are
+    /// only displayed once enabled with [`Table::set_colors`].
+    pub fn with_colors(mut self, rules: ColorRules) -> TableBuilder {
+        fn set_colors(entry: &mut Entry, rules: ColorRules) {
+            match entry {
+                Entry::Field(field) => field.display.colors = rules,
+                Entry::Group(group) => {
+                    for entry in group.entries.iter_mut() {
+                        set_colors(entry, rules);
+                    }
+                }
+            }
+        }
+        if let Some(entry) = self.header.last_mut() {
+            set_colors(entry, rules);
+        }
+        self
+    }
+
+    /// Set the number of values displayed by the last added field, if it's a
+    /// [`DisplayKind::Sparkline`] field.
+    pub fn with_sparkline_len(mut self, len: usize) -> TableBuilder {
+        if let Some(Entry::Field(field)) = self.header.last_mut() {
+            field.history = History::new(len);
+            if matches!(field.display.display_kind, DisplayKind::Sparkline) {
+                field.display.len = width(&field.name).max(len);
+            }
+        }
+        self
+    }
+
+    /// Set the description of the last added entry, displayed by
+    /// [`Table::legend`].
+    pub fn with_description(mut self, description: &str) -> TableBuilder {
+        fn set_description(entry: &mut Entry, description: &str) {
+            match entry {
+                Entry::Field(field) => field.description = Some(description.to_string()),
+                Entry::Group(group) => {
+                    for entry in group.entries.iter_mut() {
+                        set_description(entry, description);
+                    }
+                }
+            }
+        }
+        if let Some(entry) = self.header.last_mut() {
+            set_description(entry, description);
+        }
+        self
+    }
+
+    fn push_field(
+        mut self,
+        name: &str,
+        display_kind: DisplayKind,
+        binding: Option<Binding>,
+    ) -> TableBuilder {
+        let bar = Bar::default();
+        let history = History::default();
+        let (len, align) = match display_kind {
+            DisplayKind::Histogram => (width(name).max(bar.width()), Align::Left),
+            DisplayKind::Sparkline => (width(name).max(history.len()), Align::Left),
+            _ => (width(name), Align::Right),
+        };
+        self.header.push(Entry::Field(Box::new(Field {
             name: name.to_string(),
             display: DisplayInfo {
-                len: name.len(),
-                align: if matches!(display_kind, DisplayKind::Histogram) {
-                    Align::Left
-                } else {
-                    Align::Right
-                },
+                len,
+                align,
                 display_kind,
                 margin_left: 0,
+                unit: None,
+                bar,
+                colors: ColorRules::default(),
             },
-            last_value: Value::Int(0),
+            last_value: Value::Empty,
+            history,
+            stats: FieldStats::default(),
             full_path: vec![],
-        }));
+            cell_path: Arc::default(),
+            labels: Arc::default(),
+            binding,
+            description: None,
+        })));
         self
     }
 
     pub fn build(self) -> Table {
-        let mut header_lines = Vec::new();
         let mut header = self.header;
 
         let depth = depth(&header);
-        header_lines.resize_with(depth, Default::default);
         force_uniform_depth(&mut header, depth);
         compute_field_paths(&mut header, vec![]);
-        fill_header_lines(&mut header, depth, &mut header_lines);
-        header_lines
-            .iter_mut()
-            .for_each(|x| *x = x.trim_end().to_string());
+        allocate_widths(&mut header);
 
-        let mut fields = collect_fields(header);
+        let mut fields = Vec::new();
+        let columns = collect_fields(header, &mut fields);
         add_padding(&mut fields);
 
         Table {
-            header_lines,
+            columns,
             fields,
+            last_row: None,
+            number_format: NumberFormat::default(),
+            colors: false,
         }
     }
 }
 
 /// Make sure all entries have the given depth by inserting empty groups
 /// around entries.
-fn force_uniform_depth(entries: &mut Vec<Entry>, expected_depth: usize) {
+fn force_uniform_depth(entries: &mut [Entry], expected_depth: usize) {
     for entry in entries.iter_mut() {
         let entry_depth = match entry {
-            Entry::Group(group) => depth(&group.entries) + 1,
+            Entry::Group(group) => {
+                let group_depth = depth(&group.entries);
+                force_uniform_depth(&mut group.entries, group_depth);
+                group_depth + 1
+            }
             Entry::Field(_) => 1,
         };
         if entry_depth < expected_depth {
@@ -84,7 +272,7 @@ fn force_uniform_depth(entries: &mut Vec<Entry>, expected_depth: usize) {
 }
 
 /// Fill the field full_path by traversing the tree
-fn compute_field_paths(entries: &mut Vec<Entry>, path: Vec<String>) {
+fn compute_field_paths(entries: &mut [Entry], path: Vec<String>) {
     for entry in entries.iter_mut() {
         match entry {
             Entry::Group(group) => {
@@ -95,24 +283,45 @@ fn compute_field_paths(entries: &mut Vec<Entry>, path: Vec<String>) {
             Entry::Field(field) => {
                 field.full_path = path.clone();
                 field.full_path.push(field.name.clone());
+                field.cell_path = field
+                    .full_path
+                    .iter()
+                    .filter(|x| !x.is_empty())
+                    .cloned()
+                    .collect();
+                if let Some(binding) = &field.binding {
+                    field.labels = binding
+                        .key
+                        .labels()
+                        .map(|label| (label.key().to_string(), label.value().to_string()))
+                        .collect();
+                }
             }
         };
     }
 }
 
-fn collect_fields(entries: Vec<Entry>) -> Vec<Field> {
-    let mut result = Vec::new();
+/// Move the fields to `fields`, in display order, and return the layout of
+/// the header referencing them by index.
+fn collect_fields(entries: Vec<Entry>, fields: &mut Vec<Field>) -> Vec<Column> {
+    let mut columns = Vec::new();
     for entry in entries {
-        match entry {
-            Entry::Group(group) => result.append(&mut collect_fields(group.entries)),
-            Entry::Field(field) => result.push(field),
-        }
+        columns.push(match entry {
+            Entry::Group(group) => Column::Group {
+                name: group.name,
+                columns: collect_fields(group.entries, fields),
+            },
+            Entry::Field(field) => {
+                fields.push(*field);
+                Column::Field(fields.len() - 1)
+            }
+        });
     }
-    result
+    columns
 }
 
 // Add to fields padding for the separator
-fn add_padding(fields: &mut Vec<Field>) {
+fn add_padding(fields: &mut [Field]) {
     for i in 1..fields.len() {
         let path1 = &fields[i - 1].full_path;
         let path2 = &fields[i].full_path;
@@ -126,7 +335,7 @@ fn add_padding(fields: &mut Vec<Field>) {
     }
 }
 
-fn depth(entries: &Vec<Entry>) -> usize {
+fn depth(entries: &[Entry]) -> usize {
     entries
         .iter()
         .map(|entry| match entry {
@@ -137,89 +346,179 @@ fn depth(entries: &Vec<Entry>) -> usize {
         .unwrap_or(0)
 }
 
-fn fill_header_lines(entries: &mut Vec<Entry>, depth: usize, mut lines: &mut Vec<String>) -> usize {
-    let mut len = 0;
-    let mut it = entries.iter_mut().peekable();
-    while let Some(entry) = it.next() {
-        match entry {
-            Entry::Group(ref mut group) => {
-                let i = lines.len() - depth;
-                let mut child_len = fill_header_lines(&mut group.entries, depth - 1, &mut lines);
-                // enlarge child to fit parent
-                while child_len < group.name.len() {
-                    for j in (i + 1)..lines.len() {
-                        lines[j].push(' ');
-                    }
-                    child_len += 1;
-                    let mut g: &mut Group = group;
-                    loop {
-                        match g.entries.last_mut().expect("empty group") {
-                            Entry::Group(ref mut group) => g = group,
-                            Entry::Field(last_field) => {
-                                last_field.display.len += 1;
-                                break;
-                            }
-                        }
-                    }
+/// Separator between sibling entries: groups are separated by a vertical
+/// line, fields by a space. Siblings are all groups or all fields because of
+/// force_uniform_depth.
+fn separator(first_sibling_is_group: bool) -> &'static str {
+    if first_sibling_is_group {
+        " | "
+    } else {
+        " "
+    }
+}
+
+/// Compute the width of the entries, including their separators, from the
+/// width of their fields (bottom-up). A group whose name is wider than its
+/// entries shares the extra columns among them (top-down), so that values stay
+/// aligned with the header.
+fn allocate_widths(entries: &mut [Entry]) -> usize {
+    let is_group = matches!(entries.first(), Some(Entry::Group(_)));
+    let mut total = width(separator(is_group)) * entries.len().saturating_sub(1);
+    for entry in entries.iter_mut() {
+        total += match entry {
+            Entry::Field(field) => field.display.len,
+            Entry::Group(group) => {
+                let entries_width = allocate_widths(&mut group.entries);
+                let name_width = width(&group.name);
+                if name_width > entries_width {
+                    widen(&mut group.entries, name_width - entries_width);
                 }
-                len += add_centered_str(&mut lines[i], &group.name, child_len);
+                entries_width.max(name_width)
+            }
+        };
+    }
+    total
+}
 
-                // Join groups with " | "
-                if depth != 1 && it.peek().is_some() {
-                    for j in i..lines.len() {
-                        lines[j].push_str(&" | ");
-                    }
-                    len += 3;
+/// Share extra columns among the entries and, recursively, their fields. The
+/// remainder goes to the last entries.
+fn widen(entries: &mut [Entry], extra: usize) {
+    let count = entries.len();
+    for (i, entry) in entries.iter_mut().enumerate() {
+        let share = extra / count + usize::from(i >= count - extra % count);
+        match entry {
+            Entry::Field(field) => field.display.len += share,
+            Entry::Group(group) => widen(&mut group.entries, share),
+        }
+    }
+}
+
+/// Render the header of the columns with their current width, one line per
+/// level, and return the lines along with their width.
+fn header_lines(columns: &[Column], fields: &[Field]) -> (Vec<String>, usize) {
+    let is_group = matches!(columns.first(), Some(Column::Group { .. }));
+    let separator = separator(is_group);
+    let mut lines: Vec<String> = Vec::new();
+    let mut total = 0;
+    for (i, column) in columns.iter().enumerate() {
+        let (column_lines, column_width) = match column {
+            Column::Group { name, columns } => {
+                let (entries_lines, entries_width) = header_lines(columns, fields);
+                let mut label = String::new();
+                // Only the name of a group split across panels can be wider
+                // than its entries
+                let name = tui::truncate(name, entries_width);
+                let column_width = add_centered_str(&mut label, &name, entries_width);
+                let mut column_lines = vec![label];
+                for mut line in entries_lines {
+                    // Fields only grow, so the name can't be wider than them
+                    line.extend(std::iter::repeat_n(' ', column_width - entries_width));
+                    column_lines.push(line);
                 }
+                (column_lines, column_width)
             }
-            Entry::Field(field) => {
-                if depth == 1 {
-                    lines.last_mut().unwrap().push_str(&field.name);
-                    len += field.display.len;
-                } else {
-                    // Unreachable because of force_uniform_depth
-                    unreachable!();
+            Column::Field(index) => {
+                let field = &fields[*index];
+                let mut line = String::new();
+                let padding = field.display.len.saturating_sub(width(&field.name));
+                // Names are aligned like the values
+                match field.display.align {
+                    Align::Left => {
+                        line.push_str(&field.name);
+                        line.extend(std::iter::repeat_n(' ', padding));
+                    }
+                    Align::Right => {
+                        line.extend(std::iter::repeat_n(' ', padding));
+                        line.push_str(&field.name);
+                    }
                 }
+                (vec![line], field.display.len.max(width(&field.name)))
             }
+        };
+        if i == 0 {
+            lines = column_lines;
+        } else {
+            for (line, column_line) in lines.iter_mut().zip(column_lines) {
+                line.push_str(separator);
+                line.push_str(&column_line);
+            }
+            total += width(separator);
         }
-        // Join terminal fields with " "
-        if depth == 1 && it.peek().is_some() {
-            lines.last_mut().unwrap().push(' ');
-            len += 1;
-        }
+        total += column_width;
     }
-    len
+    (lines, total)
 }
 
-/// Append value to output, making sure it takes at least minimum_len characters.
-/// Return the number of added characters. If minimum_len is bigger than value.len(),
-/// text will be centered.
+/// Keep the columns of the fields in `panel`, and the groups containing them.
+fn clip(columns: &[Column], panel: &Range<usize>) -> Vec<Column> {
+    columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Group { name, columns } => {
+                let columns = clip(columns, panel);
+                (!columns.is_empty()).then(|| Column::Group {
+                    name: name.clone(),
+                    columns,
+                })
+            }
+            Column::Field(i) => panel.contains(i).then_some(Column::Field(*i)),
+        })
+        .collect()
+}
+
+/// Append value to output, making sure it takes at least minimum_len columns.
+/// Return the number of added columns. If minimum_len is bigger than the width
+/// of value, text will be centered.
 fn add_centered_str(output: &mut String, value: &str, minimum_len: usize) -> usize {
-    let initial_len = output.len();
-    if minimum_len > value.len() {
-        let extra_space_to_insert = minimum_len - value.len();
+    let value_len = width(value);
+    if minimum_len > value_len {
+        let extra_space_to_insert = minimum_len - value_len;
         for _ in 0..((extra_space_to_insert) / 2) {
             output.push(' ');
         }
         output.push_str(value);
-        for _ in 0..((extra_space_to_insert + 1) / 2) {
+        for _ in 0..extra_space_to_insert.div_ceil(2) {
             output.push(' ');
         }
+        minimum_len
     } else {
         output.push_str(value);
+        value_len
     }
-    output.len() - initial_len
+}
+
+/// Number of terminal columns taken by the string: wide characters such as
+/// CJK take two columns, combining marks none.
+pub(crate) fn width(value: &str) -> usize {
+    value.width()
 }
 
 pub struct Table {
-    header_lines: Vec<String>,
+    /// Layout of the header
+    columns: Vec<Column>,
     fields: Vec<Field>,
+    /// When the last row was displayed, used to compute rates
+    last_row: Option<Instant>,
+    number_format: NumberFormat,
+    /// Whether the color rules of the fields are applied
+    colors: bool,
 }
 
 #[derive(Clone, Debug)]
 enum Entry {
     Group(Group),
-    Field(Field),
+    Field(Box<Field>),
+}
+
+/// A header entry of a built table
+#[derive(Clone, Debug)]
+enum Column {
+    Group {
+        name: String,
+        columns: Vec<Column>,
+    },
+    /// Index of the field
+    Field(usize),
 }
 
 #[derive(Clone, Debug)]
@@ -232,8 +531,88 @@ struct Group {
 struct Field {
     name: String,
     full_path: Vec<String>,
+    /// Path without the empty groups and labels of the binding, shared by
+    /// the cells of the field
+    cell_path: Arc<[String]>,
+    labels: Arc<[(String, String)]>,
     display: DisplayInfo,
+    /// Value of the previous row, Empty before the first one
     last_value: Value,
+    /// Last values displayed by a sparkline
+    history: History,
+    /// Statistics of the displayed values since the first row
+    stats: FieldStats,
+    binding: Option<Binding>,
+    description: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct FieldStats {
+    min: Option<Value>,
+    max: Option<Value>,
+    sum: f64,
+    count: u64,
+}
+
+impl FieldStats {
+    fn update(&mut self, value: &Value) {
+        let x = match value {
+            Value::Int(x) => *x as f64,
+            Value::F64(x) if !x.is_nan() => *x,
+            _ => return,
+        };
+        if self.min.as_ref().is_none_or(|min| x < min.as_f64()) {
+            self.min = Some(value.clone());
+        }
+        if self.max.as_ref().is_none_or(|max| x > max.as_f64()) {
+            self.max = Some(value.clone());
+        }
+        self.sum += x;
+        self.count += 1;
+    }
+
+    fn avg(&self) -> Value {
+        if self.count == 0 {
+            Value::Empty
+        } else {
+            Value::F64(self.sum / self.count as f64)
+        }
+    }
+}
+
+impl Field {
+    /// Full path joined with dots, ignoring the empty groups used for alignment
+    fn path(&self) -> String {
+        join_path(&self.full_path)
+    }
+
+    /// Path of the metric displayed by the field: the path of the group for
+    /// the statistics of a histogram.
+    fn metric_path(&self) -> String {
+        match &self.binding {
+            Some(Binding { stat: Some(_), .. }) => {
+                join_path(&self.full_path[..self.full_path.len() - 1])
+            }
+            _ => self.path(),
+        }
+    }
+}
+
+fn join_path(path: &[String]) -> String {
+    let path: Vec<&str> = path
+        .iter()
+        .filter(|x| !x.is_empty())
+        .map(|x| x.as_str())
+        .collect();
+    path.join(".")
+}
+
+/// The metric displayed by a field
+#[derive(Clone, Debug, PartialEq)]
+struct Binding {
+    key: Key,
+    /// For histograms, the displayed statistic
+    stat: Option<Stat>,
 }
 
 #[derive(Clone, Debug)]
@@ -243,6 +622,11 @@ struct DisplayInfo {
     margin_left: usize,
     align: Align,
     display_kind: DisplayKind,
+    /// Unit of the displayed values, if known
+    unit: Option<Unit>,
+    /// How [`DisplayKind::Histogram`] values are drawn
+    bar: Bar,
+    colors: ColorRules,
 }
 
 #[derive(Clone, Debug)]
@@ -251,17 +635,26 @@ enum Align {
     Left,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum DisplayKind {
     Number,
+    /// Difference from the value in the previous row
     Difference,
+    /// Difference from the value in the previous row, divided by the seconds
+    /// elapsed since then
+    Rate,
     Histogram,
+    /// The last values, drawn as a strip such as `▁▂▃▅▇`
+    Sparkline,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Int(i64),
     F64(f64),
+    /// No value is available, e.g. a histogram without samples.
+    Empty,
 }
 
 impl From<i64> for Value {
@@ -276,11 +669,22 @@ impl From<f64> for Value {
     }
 }
 
+impl Value {
+    fn as_f64(&self) -> f64 {
+        match self {
+            Value::Int(x) => *x as f64,
+            Value::F64(x) => *x,
+            Value::Empty => f64::NAN,
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Int(x) => write!(f, "{}", x),
             Value::F64(x) => write!(f, "{}", x),
+            Value::Empty => write!(f, "-"),
         }
     }
 }
@@ -288,7 +692,130 @@ impl Display for Value {
 // positional?
 impl Table {
     pub fn header(&self) -> String {
-        self.header_lines.join("\n")
+        self.panel_header(0..self.fields.len())
+    }
+
+    /// Header of the fields of a panel returned by [`Table::panels`], with
+    /// the names of the groups containing them.
+    pub fn panel_header(&self, panel: Range<usize>) -> String {
+        let (lines, _) = header_lines(&clip(&self.columns, &panel), &self.fields);
+        let lines: Vec<&str> = lines.iter().map(|line| line.trim_end()).collect();
+        lines.join("\n")
+    }
+
+    /// Split the fields into panels, as ranges of field indices, whose rows
+    /// are no wider than `max_width` columns. Panels end at a group boundary
+    /// when possible, and a field wider than `max_width` gets its own panel.
+    pub fn panels(&self, max_width: usize) -> Vec<Range<usize>> {
+        let mut panels = Vec::new();
+        let mut start = 0;
+        while start < self.fields.len() {
+            let mut width = self.fields[start].display.len;
+            let mut end = start + 1;
+            while let Some(field) = self.fields.get(end) {
+                // Fields are separated by a space, and groups by the margin
+                // of their first field too
+                let field_width = field.display.margin_left + 1 + field.display.len;
+                if width + field_width > max_width {
+                    break;
+                }
+                width += field_width;
+                end += 1;
+            }
+            if self
+                .fields
+                .get(end)
+                .is_some_and(|field| field.display.margin_left == 0)
+            {
+                // Break at the last group boundary rather than within a group
+                if let Some(boundary) =
+                    (start + 1..end).rfind(|i| self.fields[*i].display.margin_left > 0)
+                {
+                    end = boundary;
+                }
+            }
+            panels.push(start..end);
+            start = end;
+        }
+        panels
+    }
+
+    /// Number of fields, which is the number of values expected by [`Table::display_row`].
+    pub fn field_count(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Dotted paths of the fields, in display order.
+    pub fn field_paths(&self) -> Vec<String> {
+        self.fields.iter().map(Field::path).collect()
+    }
+
+    /// Keys of all the metrics bound to a field.
+    pub(crate) fn keys(&self) -> HashSet<&Key> {
+        self.fields
+            .iter()
+            .filter_map(|field| field.binding.as_ref().map(|binding| &binding.key))
+            .collect()
+    }
+
+    /// Dotted paths of the fields of `self` whose metric is not displayed by `other`.
+    pub(crate) fn fields_missing_from(&self, other: &Table) -> Vec<String> {
+        let other_keys = other.keys();
+        self.fields
+            .iter()
+            .filter(|field| match &field.binding {
+                Some(binding) => !other_keys.contains(&binding.key),
+                None => false,
+            })
+            .map(Field::path)
+            .collect()
+    }
+
+    /// Take over the state of the fields of `previous` bound to the same
+    /// metric, so that differences and rates are not reset when the table
+    /// layout changes, and fields which grew to fit their values keep their
+    /// width.
+    pub(crate) fn keep_state_of(&mut self, previous: &Table) {
+        for field in self.fields.iter_mut() {
+            let previous_field = previous
+                .fields
+                .iter()
+                .find(|x| x.binding.is_some() && x.binding == field.binding);
+            if let Some(previous_field) = previous_field {
+                field.last_value = previous_field.last_value.clone();
+                field.history = previous_field.history.clone();
+                field.stats = previous_field.stats.clone();
+                field.display.len = field.display.len.max(previous_field.display.len);
+            }
+        }
+        self.last_row = previous.last_row;
+    }
+
+    /// Return the position of the fields bound to the given metric key, along
+    /// with the histogram statistic they display.
+    pub(crate) fn bindings_of<'a>(
+        &'a self,
+        key: &'a Key,
+    ) -> impl Iterator<Item = (usize, Option<Stat>)> + 'a {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, field)| match &field.binding {
+                Some(binding) if &binding.key == key => Some((i, binding.stat)),
+                _ => None,
+            })
+    }
+
+    /// Set how values are formatted by [`Table::display_row`], according to
+    /// the unit of each field. CSV rows are never formatted.
+    pub fn set_number_format(&mut self, number_format: NumberFormat) {
+        self.number_format = number_format;
+    }
+
+    /// Enable the ANSI colors set with [`TableBuilder::with_colors`] in the
+    /// rows returned by [`Table::display_row`]. Disabled by default.
+    pub fn set_colors(&mut self, colors: bool) {
+        self.colors = colors;
     }
 
     // Given a list of path components, with the last one being the field and
@@ -306,70 +833,381 @@ impl Table {
     }
 
     // Each entry gets an associated index at build time, field should be supplied in order
-    pub fn display_row<T>(&mut self, values: Vec<T>) -> String
+    pub fn display_row<T>(&mut self, values: impl IntoIterator<Item = T>) -> String
+    where
+        T: Into<Value>,
+    {
+        self.display_row_at(values, Instant::now())
+    }
+
+    /// Same as [`Table::display_row`], with an explicit sampling time for
+    /// [`DisplayKind::Rate`] fields.
+    pub fn display_row_at<T>(&mut self, values: impl IntoIterator<Item = T>, now: Instant) -> String
     where
         T: Into<Value>,
     {
+        let cells = self.row_at(values, now);
+        self.display_cells(&cells)
+    }
+
+    /// Render the cells returned by [`Table::row_at`], aligned with the header.
+    pub fn display_cells(&mut self, cells: &[Cell]) -> String {
+        self.display_panel(cells, 0..self.fields.len())
+    }
+
+    /// Render the cells of the fields of a panel returned by [`Table::panels`],
+    /// aligned with [`Table::panel_header`].
+    pub fn display_panel(&mut self, cells: &[Cell], panel: Range<usize>) -> String {
+        let values = cells
+            .iter()
+            .map(|cell| cell.display_value.clone())
+            .collect();
+        self.render_row(values, panel, true)
+    }
+
+    /// Row with the declared unit of each field, e.g. `B` or `/s`, aligned like
+    /// [`Table::display_row`].
+    pub fn unit_row(&mut self) -> String {
+        self.panel_unit_row(0..self.fields.len())
+    }
+
+    /// Row with the unit of the fields of a panel, aligned like
+    /// [`Table::display_panel`].
+    pub fn panel_unit_row(&mut self, panel: Range<usize>) -> String {
         let mut output = String::new();
-        for (value, mut field) in values.into_iter().zip(&mut self.fields) {
-            for _ in 0..field.display.margin_left {
-                output.push(' ');
+        for (i, field) in self.fields[panel].iter_mut().enumerate() {
+            if i > 0 {
+                for _ in 0..field.display.margin_left {
+                    output.push(' ');
+                }
+            }
+            let unit = match &field.display.unit {
+                Some(unit) => unit.as_canonical_label().to_string(),
+                None => String::new(),
+            };
+            display_field(&mut output, field, unit, None);
+            output.push(' ');
+        }
+        output.trim_end().to_string()
+    }
+
+    /// Lines with the description of each described field, as
+    /// `path: description`. The fields of a histogram share a single line.
+    pub fn legend(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for field in &self.fields {
+            if let Some(description) = &field.description {
+                let line = format!("{}: {}", field.metric_path(), description);
+                if lines.last() != Some(&line) {
+                    lines.push(line);
+                }
             }
+        }
+        lines
+    }
+
+    /// Rows with the minimum, maximum and average of the values displayed by
+    /// each field since the first row, aligned like [`Table::display_row`].
+    pub fn stats_rows(&mut self) -> [String; 3] {
+        self.panel_stats_rows(0..self.fields.len())
+    }
+
+    /// Rows of [`Table::stats_rows`] for the fields of a panel, aligned like
+    /// [`Table::display_panel`].
+    pub fn panel_stats_rows(&mut self, panel: Range<usize>) -> [String; 3] {
+        let min = self.stat_values(|stats| stats.min.clone().unwrap_or(Value::Empty));
+        let max = self.stat_values(|stats| stats.max.clone().unwrap_or(Value::Empty));
+        let avg = self.stat_values(FieldStats::avg);
+        [
+            self.render_row(min, panel.clone(), false),
+            self.render_row(max, panel.clone(), false),
+            self.render_row(avg, panel, false),
+        ]
+    }
+
+    fn stat_values(&self, f: impl Fn(&FieldStats) -> Value) -> Vec<Value> {
+        self.fields.iter().map(|field| f(&field.stats)).collect()
+    }
+
+    /// Render the values of the fields of the panel. Sparkline fields display
+    /// their history, unless `sparklines` is false.
+    fn render_row(&mut self, values: Vec<Value>, panel: Range<usize>, sparklines: bool) -> String {
+        let mut output = String::new();
+        let values = values.into_iter().skip(panel.start);
+        for (i, (value, field)) in values.zip(&mut self.fields[panel]).enumerate() {
+            // The margin separates the field from the previous group
+            if i > 0 {
+                for _ in 0..field.display.margin_left {
+                    output.push(' ');
+                }
+            }
+            let color = if self.colors {
+                field.display.colors.color_of(&value)
+            } else {
+                None
+            };
+            let text = cell_text(field, &value, &self.number_format, sparklines);
+            display_field(&mut output, field, text, color);
+            output.push(' ');
+        }
+        output.pop();
+        output
+    }
+
+    /// Lines of the vertical layout of the cells returned by [`Table::row_at`]:
+    /// groups and fields one per line, fields being indented under their
+    /// group and followed by their value. Fields which don't display their
+    /// value as is, such as differences, rates and sparklines, also display it
+    /// after the value.
+    pub fn vertical_lines(&self, cells: &[Cell]) -> Vec<String> {
+        let mut names = Vec::new();
+        vertical_names(&self.columns, &self.fields, 0, &mut names);
+        let values: Vec<String> = cells
+            .iter()
+            .map(|cell| self.number_format.format(&cell.value, cell.unit))
+            .collect();
+        let name_width = names
+            .iter()
+            .filter(|(_, i)| i.is_some())
+            .map(|(name, _)| width(name))
+            .max()
+            .unwrap_or(0);
+        let value_width = values.iter().map(|x| width(x)).max().unwrap_or(0);
+        let mut lines = Vec::with_capacity(names.len());
+        for (name, i) in names {
+            let mut line = name;
+            let (Some(field), Some(cell), Some(value)) = (
+                i.and_then(|i| self.fields.get(i)),
+                i.and_then(|i| cells.get(i)),
+                i.and_then(|i| values.get(i)),
+            ) else {
+                lines.push(line);
+                continue;
+            };
+            let color = if self.colors {
+                field.display.colors.color_of(&cell.display_value)
+            } else {
+                None
+            };
+            let colored = |text: &str| match color {
+                Some(color) => format!("{}{}{}", color, text, RESET),
+                None => text.to_string(),
+            };
+            line.push_str(&" ".repeat(name_width - width(&line) + 2));
+            line.push_str(&" ".repeat(value_width - width(value)));
+            if field.display.display_kind == DisplayKind::Number {
+                line.push_str(&colored(value));
+            } else {
+                line.push_str(value);
+                line.push_str("  ");
+                let text = cell_text(field, &cell.display_value, &self.number_format, true);
+                line.push_str(&colored(&text));
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines
+    }
+
+    /// Header of a CSV (or TSV, depending on the separator) table: the dotted
+    /// path of each field.
+    pub fn csv_header(&self, separator: char) -> String {
+        let paths: Vec<String> = self
+            .field_paths()
+            .iter()
+            .map(|path| csv_escape(path, separator))
+            .collect();
+        paths.join(&separator.to_string())
+    }
+
+    /// Same as [`Table::display_row_at`], but returns a CSV (or TSV, depending
+    /// on the separator) line. Values are not aligned and histogram bars are
+    /// displayed as numbers.
+    pub fn csv_row_at<T>(
+        &mut self,
+        values: impl IntoIterator<Item = T>,
+        now: Instant,
+        separator: char,
+    ) -> String
+    where
+        T: Into<Value>,
+    {
+        let cells = self.row_at(values, now);
+        Self::csv_cells(&cells, separator)
+    }
+
+    /// Render the cells returned by [`Table::row_at`] as a CSV (or TSV) line.
+    pub fn csv_cells(cells: &[Cell], separator: char) -> String {
+        let values: Vec<String> = cells
+            .iter()
+            .map(|cell| match &cell.display_value {
+                Value::Empty => String::new(),
+                value => csv_escape(&value.to_string(), separator),
+            })
+            .collect();
+        values.join(&separator.to_string())
+    }
+
+    /// Compute the cells of the next row from the value of each field, in
+    /// display order: the value displayed by each field, such as the
+    /// difference from the previous row, is derived from the value, and the
+    /// state needed for the next row is remembered.
+    pub fn row_at<T>(&mut self, values: impl IntoIterator<Item = T>, now: Instant) -> Vec<Cell>
+    where
+        T: Into<Value>,
+    {
+        let elapsed = self
+            .last_row
+            .map(|last_row| now.saturating_duration_since(last_row).as_secs_f64())
+            .filter(|elapsed| *elapsed > 0.0);
+        self.last_row = Some(now);
+        let mut cells = Vec::with_capacity(self.fields.len());
+        for (value, field) in values.into_iter().zip(&mut self.fields) {
             let value = value.into();
-            match field.display.display_kind {
-                DisplayKind::Number => display_field(&mut output, field, value.to_string()),
+            let display_value = match field.display.display_kind {
+                DisplayKind::Number | DisplayKind::Histogram => value.clone(),
+                DisplayKind::Sparkline => {
+                    field.history.push(&value);
+                    value.clone()
+                }
                 DisplayKind::Difference => {
                     let difference = match (&field.last_value, &value) {
                         (Value::Int(x), Value::Int(y)) => Value::Int(y - x),
                         (Value::F64(x), Value::F64(y)) => Value::F64(y - x),
                         (_, new_val) => new_val.clone(),
                     };
-                    field.last_value = value;
-                    display_field(&mut output, field, difference.to_string());
+                    field.last_value = value.clone();
+                    difference
                 }
-                DisplayKind::Histogram => {
-                    display_field(
-                        &mut output,
-                        field,
-                        "#".repeat(match value {
-                            Value::Int(x) => x as usize,
-                            Value::F64(x) => x as usize,
-                        }),
-                    );
+                DisplayKind::Rate => {
+                    // The first row has no previous sample to compare with
+                    let rate = match (elapsed, &field.last_value, &value) {
+                        (Some(secs), Value::Int(x), Value::Int(y)) => {
+                            Value::F64((y - x) as f64 / secs)
+                        }
+                        (Some(secs), Value::F64(x), Value::F64(y)) => Value::F64((y - x) / secs),
+                        _ => Value::Empty,
+                    };
+                    field.last_value = value.clone();
+                    rate
                 }
+            };
+            field.stats.update(&display_value);
+            cells.push(Cell {
+                path: field.cell_path.clone(),
+                labels: field.labels.clone(),
+                stat: field.binding.as_ref().and_then(|binding| binding.stat),
+                unit: field.display.unit,
+                display_kind: field.display.display_kind.clone(),
+                value,
+                display_value,
+            });
+        }
+        cells
+    }
+}
+
+/// Text of a value displayed by the field: the formatted number, or its bar.
+/// Sparkline fields display their history, unless `sparklines` is false.
+fn cell_text(
+    field: &Field,
+    value: &Value,
+    number_format: &NumberFormat,
+    sparklines: bool,
+) -> String {
+    match field.display.display_kind {
+        DisplayKind::Sparkline if sparklines => field.history.render(),
+        DisplayKind::Number
+        | DisplayKind::Difference
+        | DisplayKind::Rate
+        | DisplayKind::Sparkline => number_format.format(value, field.display.unit),
+        DisplayKind::Histogram => {
+            let max = field.stats.max.as_ref().map_or(f64::NAN, Value::as_f64);
+            let mut bar = field.display.bar.render_with_max(value.as_f64(), max);
+            if field.display.bar.annotated() {
+                let value = number_format.format(value, field.display.unit);
+                field.display.bar.annotate(&mut bar, &value);
             }
-            output.push(' ');
+            bar
         }
-        output.pop();
-        output
+    }
+}
+
+/// Names of the groups and fields of the vertical layout, indented under
+/// their group, along with the index of the fields. The empty groups used for
+/// alignment are skipped.
+fn vertical_names(
+    columns: &[Column],
+    fields: &[Field],
+    indent: usize,
+    names: &mut Vec<(String, Option<usize>)>,
+) {
+    for column in columns {
+        match column {
+            Column::Group { name, columns } if name.is_empty() => {
+                vertical_names(columns, fields, indent, names)
+            }
+            Column::Group { name, columns } => {
+                names.push((format!("{:indent$}{}", "", name), None));
+                vertical_names(columns, fields, indent + VERTICAL_INDENT, names);
+            }
+            Column::Field(i) => {
+                names.push((format!("{:indent$}{}", "", fields[*i].name), Some(*i)));
+            }
+        }
+    }
+}
+
+/// Quote a CSV value if it contains the separator, quotes or line breaks.
+fn csv_escape(value: &str, separator: char) -> String {
+    if value.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
     }
 }
 
 const AUTOMATIC_GROWTH_MARGIN: usize = 1;
 
-fn display_field(output: &mut String, field: &mut Field, v: String) {
-    if field.display.len < v.len() {
+/// Indentation of the entries of a group in the vertical layout
+const VERTICAL_INDENT: usize = 2;
+
+/// Append the value padded to the field width. The color is applied to the
+/// value only, and doesn't count in the width.
+fn display_field(output: &mut String, field: &mut Field, v: String, color: Option<&str>) {
+    // Units such as µs and bars take more bytes than columns
+    let width = width(&v);
+    if field.display.len < width {
         // When a table cell is asked to display a value too big for it's allocated space
         // (field.display.len), we'll automatically enlarge that cell to make it fit that
         // value.
         // To prevent too many size changes:
         // - the cell enlargement is permanent
         // - we add an extra AUTOMATIC_GROWTH_MARGIN space
-        field.display.len = v.len() + AUTOMATIC_GROWTH_MARGIN;
+        field.display.len = width + AUTOMATIC_GROWTH_MARGIN;
     }
+    let push_value = |output: &mut String| match color {
+        Some(color) => {
+            output.push_str(color);
+            output.push_str(&v);
+            output.push_str(RESET);
+        }
+        None => output.push_str(&v),
+    };
     if matches!(field.display.align, Align::Left) {
-        output.push_str(&v);
+        push_value(output);
     }
-    for _ in 0..(field.display.len - v.len()) {
+    for _ in 0..(field.display.len - width) {
         output.push(' ')
     }
     if matches!(field.display.align, Align::Right) {
-        output.push_str(&v);
+        push_value(output);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use super::*;
 
     fn table_a() -> Table {
@@ -422,6 +1260,11 @@ mod tests {
         let actual = table.display_row(vec![2, 324]);
         let expected = "           2      324";
         assert_eq!(actual, expected);
+        // The header follows the enlarged fields
+        assert_eq!(
+            table.header(),
+            ["        input", "     counter counter2"].join("\n")
+        );
     }
 
     fn table_b() -> Table {
@@ -452,6 +1295,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn header_with_wide_characters() {
+        // "请求" takes 4 columns, "é" is "e" followed by a combining accent
+        let mut table = TableBuilder::new()
+            .group("请求", |input| {
+                input
+                    .field("c1", DisplayKind::Number)
+                    .field("c2", DisplayKind::Number)
+            })
+            .group("g2", |input| {
+                input
+                    .field("cafe\u{301}", DisplayKind::Number)
+                    .field("数", DisplayKind::Number)
+            })
+            .build();
+        assert_eq!(
+            table.header(),
+            [
+                //
+                "请求  |   g2",
+                "c1 c2 | cafe\u{301} 数",
+            ]
+            .join("\n")
+        );
+        assert_eq!(table.display_row(vec![1, 2, 3, 4]), " 1  2      3  4");
+    }
+
     #[test]
     fn index_with_multiple_groups() {
         let table = table_b();
@@ -494,10 +1364,9 @@ mod tests {
     fn table_with_large_group_label() {
         // Make sure column headers which are smaller than their parent
         // group, fill their space to align. In this example, "A" should
-        // take 5 spaces, like "Large"
-        // TODO: refactor fill_header_lines and align 1 with A
+        // take 5 spaces, like "Large", and 1 is aligned with A
         let expected_l1 = "Large |";
-        let expected_l2 = "A     | B";
+        let expected_l2 = "    A | B";
         let expected_l3 = "    1   2";
 
         let mut table = TableBuilder::new()
@@ -510,6 +1379,30 @@ mod tests {
         assert_eq!(actual, expected_l3);
     }
 
+    #[test]
+    fn nested_large_group_labels() {
+        // The extra space of a group is shared among its fields, down to the
+        // nested groups
+        let mut table = TableBuilder::new()
+            .group("A very large label", |input| {
+                input
+                    .field("c1", DisplayKind::Number)
+                    .group("g", |g| g.field("c2", DisplayKind::Number))
+            })
+            .field("c3", DisplayKind::Number)
+            .build();
+        assert_eq!(
+            table.header(),
+            [
+                "A very large label |",
+                "        |    g     |",
+                "     c1 |       c2 | c3",
+            ]
+            .join("\n")
+        );
+        assert_eq!(table.display_row(vec![1, 2, 3]), "      1          2    3");
+    }
+
     #[test]
     fn value_with_multiple_groups() {
         let mut table = table_b();
@@ -525,14 +1418,178 @@ mod tests {
         assert_eq!(&table.display_row(vec![3]), " 2");
     }
 
+    #[test]
+    fn value_rate() {
+        let mut table = TableBuilder::new().field("c1", DisplayKind::Rate).build();
+        let start = Instant::now();
+        assert_eq!(&table.display_row_at(vec![1], start), " -");
+        let now = start + Duration::from_millis(250);
+        assert_eq!(&table.display_row_at(vec![3], now), " 8");
+        let now = now + Duration::from_secs(4);
+        assert_eq!(&table.display_row_at(vec![5], now), " 0.5");
+        assert_eq!(&table.display_row_at(vec![7], now), "   -");
+    }
+
+    #[test]
+    fn rate_of_new_metric() {
+        let mut previous = TableBuilder::new()
+            .metric("c1", DisplayKind::Rate, "c1")
+            .build();
+        let start = Instant::now();
+        assert_eq!(&previous.display_row_at(vec![1], start), " -");
+        let mut table = TableBuilder::new()
+            .metric("c1", DisplayKind::Rate, "c1")
+            .metric("c2", DisplayKind::Rate, "c2")
+            .build();
+        table.keep_state_of(&previous);
+        // The new metric has no previous value to compute a rate from
+        let now = start + Duration::from_millis(500);
+        assert_eq!(&table.display_row_at(vec![2, 1000], now), " 2  -");
+    }
+
+    #[test]
+    fn csv() {
+        let mut table = TableBuilder::new()
+            .group("g,1", |input| {
+                input
+                    .field("c1", DisplayKind::Number)
+                    .field("c\"2", DisplayKind::Difference)
+            })
+            .field("c3", DisplayKind::Histogram)
+            .build();
+        let now = Instant::now();
+        assert_eq!(table.csv_header(','), "\"g,1.c1\",\"g,1.c\"\"2\",c3");
+        assert_eq!(table.csv_header('\t'), "g,1.c1\t\"g,1.c\"\"2\"\tc3");
+        let row = table.csv_row_at(
+            vec![Value::F64(1.5), Value::Int(3), Value::Int(4)],
+            now,
+            ',',
+        );
+        assert_eq!(row, "1.5,3,4");
+        let row = table.csv_row_at(vec![Value::Empty, Value::Int(5), Value::Int(4)], now, ',');
+        assert_eq!(row, ",2,4");
+    }
+
+    #[test]
+    fn stats() {
+        let mut table = TableBuilder::new()
+            .field("c1", DisplayKind::Number)
+            .field("c2", DisplayKind::Difference)
+            .build();
+        let now = Instant::now();
+        table.display_row_at(vec![Value::Int(4), Value::Int(1)], now);
+        table.display_row_at(vec![Value::Int(2), Value::Int(5)], now);
+        table.display_row_at(vec![Value::Empty, Value::Int(6)], now);
+        assert_eq!(table.stats_rows(), [" 2  1", " 4  4", " 3  2"]);
+    }
+
+    #[test]
+    fn value_sparkline() {
+        let mut table = TableBuilder::new()
+            .field("c1", DisplayKind::Sparkline)
+            .with_sparkline_len(3)
+            .field("c2", DisplayKind::Number)
+            .build();
+        assert_eq!(table.header(), "c1  c2");
+        assert_eq!(&table.display_row(vec![1, 1]), "  ▁  1");
+        assert_eq!(&table.display_row(vec![3, 1]), " ▁█  1");
+        assert_eq!(&table.display_row(vec![2, 1]), "▁█▅  1");
+        assert_eq!(&table.display_row(vec![1, 1]), "█▅▁  1");
+        assert_eq!(table.stats_rows(), ["1    1", "3    1", "1.75   1"]);
+    }
+
+    #[test]
+    fn colors() {
+        let mut table = TableBuilder::new()
+            .field("c1", DisplayKind::Difference)
+            .with_colors(ColorRules::new().with_trend(true).with_dim_zero(true))
+            .field("c2", DisplayKind::Number)
+            .build();
+        assert_eq!(&table.display_row(vec![3, 1]), " 3  1");
+        table.set_colors(true);
+        assert_eq!(&table.display_row(vec![5, 1]), " \x1b[32m2\x1b[0m  1");
+        assert_eq!(&table.display_row(vec![5, 1]), " \x1b[2m0\x1b[0m  1");
+        assert_eq!(&table.display_row(vec![20, 1]), "\x1b[32m15\x1b[0m  1");
+    }
+
+    #[test]
+    fn legend() {
+        let table = TableBuilder::new()
+            .histogram("latency", "latency", &[Stat::Count, Stat::Max])
+            .with_description("Request latency")
+            .field("c1", DisplayKind::Number)
+            .field("c2", DisplayKind::Number)
+            .with_description("Second column")
+            .build();
+        assert_eq!(
+            table.legend(),
+            ["latency: Request latency", "c2: Second column"]
+        );
+    }
+
+    #[test]
+    fn panels() {
+        let mut table = table_b();
+        assert_eq!(table.panels(20).len(), 1);
+        assert_eq!(table.panels(10), [0..2, 2..4]);
+        assert_eq!(table.panels(4), [0..1, 1..2, 2..3, 3..4]);
+        let cells = table.row_at(vec![1, 2, 3, 4], Instant::now());
+        assert_eq!(table.panel_header(2..4), " g2\nc3 c4");
+        assert_eq!(table.display_panel(&cells, 2..4), " 3  4");
+        // The name of a split group is cut to fit its fields
+        let mut table = TableBuilder::new()
+            .group("A large label", |group| {
+                group
+                    .field("c1", DisplayKind::Number)
+                    .field("c2", DisplayKind::Number)
+            })
+            .build();
+        let cells = table.row_at(vec![1, 2], Instant::now());
+        assert_eq!(table.panel_header(1..2), "A larg\n    c2");
+        assert_eq!(table.display_panel(&cells, 1..2), "     2");
+    }
+
+    #[test]
+    fn vertical() {
+        let mut table = TableBuilder::new()
+            .group("http", |http| {
+                http.group("requests", |requests| {
+                    requests
+                        .field("GET", DisplayKind::Difference)
+                        .field("POST", DisplayKind::Number)
+                })
+            })
+            .field("load", DisplayKind::Number)
+            .build();
+        let now = Instant::now();
+        table.row_at(vec![10, 2, 1], now);
+        let cells = table.row_at(vec![13, 1200, 1], now);
+        assert_eq!(
+            table.vertical_lines(&cells),
+            [
+                "http",
+                "  requests",
+                "    GET     13  3",
+                "    POST  1200",
+                "load         1",
+            ]
+        );
+    }
+
     #[test]
     fn value_histogram() {
         let mut table = TableBuilder::new()
             .field("c1", DisplayKind::Histogram)
             .build();
-        assert_eq!(&table.display_row(vec![1]), "# ");
-        assert_eq!(&table.display_row(vec![3]), "### ");
-        assert_eq!(&table.display_row(vec![1]), "#   ");
-        assert_eq!(&table.display_row(vec![4]), "####");
+        assert_eq!(&table.display_row(vec![1]), "██████████");
+        assert_eq!(&table.display_row(vec![4]), "██████████");
+        assert_eq!(&table.display_row(vec![1]), "██▌       ");
+        let mut table = TableBuilder::new()
+            .field("c1", DisplayKind::Histogram)
+            .with_bar(Bar::new().with_width(4).with_max(8.0).with_value(true))
+            .build();
+        assert_eq!(table.header(), "c1");
+        assert_eq!(&table.display_row(vec![2]), "█         2");
+        assert_eq!(&table.display_row(vec![16]), "████     16");
     }
 }
@@ -0,0 +1,156 @@
+//! ANSI escape sequences used by [`crate::OutputFormat::Top`] to redraw the
+//! screen in place.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Once,
+    },
+};
+
+use unicode_width::UnicodeWidthChar;
+
+/// Switch to the alternate screen and hide the cursor
+const ENTER: &str = "\x1b[?1049h\x1b[?25l";
+/// Show the cursor and go back to the main screen
+const LEAVE: &str = "\x1b[?25h\x1b[?1049l";
+/// Move the cursor to the top left corner
+const HOME: &str = "\x1b[H";
+/// Clear from the cursor to the end of the line
+const CLEAR_LINE: &str = "\x1b[K";
+/// Clear from the cursor to the end of the screen
+const CLEAR_BELOW: &str = "\x1b[J";
+
+/// Number of registers currently displaying the alternate screen
+static ACTIVE_SCREENS: AtomicUsize = AtomicUsize::new(0);
+static RESTORE_HOOKS: Once = Once::new();
+
+/// Return the sequence entering the alternate screen. The terminal is
+/// restored by writing [`leave`], on panic, or on Unix when the program is
+/// terminated by SIGINT, SIGTERM or SIGHUP.
+pub(crate) fn enter() -> &'static str {
+    RESTORE_HOOKS.call_once(|| {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // Restore the terminal before the panic message is printed
+            restore();
+            previous_hook(info);
+        }));
+        #[cfg(unix)]
+        restore_on_signals();
+    });
+    ACTIVE_SCREENS.fetch_add(1, Ordering::SeqCst);
+    ENTER
+}
+
+/// Leave the alternate screen if a register still displays it, e.g. when
+/// the program ends without dropping it.
+fn restore() {
+    if ACTIVE_SCREENS.load(Ordering::SeqCst) > 0 {
+        if io::stdout().is_terminal() {
+            _ = write!(io::stdout(), "{}", LEAVE);
+            _ = io::stdout().flush();
+        } else if io::stderr().is_terminal() {
+            _ = write!(io::stderr(), "{}", LEAVE);
+        }
+    }
+}
+
+/// Restore the terminal from a background thread when a terminating signal
+/// is received, then terminate the program as the signal would have.
+#[cfg(unix)]
+fn restore_on_signals() {
+    use signal_hook::{
+        consts::{SIGHUP, SIGINT, SIGTERM},
+        iterator::Signals,
+        low_level,
+    };
+
+    let Ok(mut signals) = Signals::new([SIGINT, SIGTERM, SIGHUP]) else {
+        return;
+    };
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            restore();
+            _ = low_level::emulate_default_handler(signal);
+        }
+    });
+}
+
+/// Return the sequence leaving the alternate screen entered with [`enter`].
+pub(crate) fn leave() -> &'static str {
+    ACTIVE_SCREENS.fetch_sub(1, Ordering::SeqCst);
+    LEAVE
+}
+
+/// Current terminal size as (columns, rows), if there is a terminal.
+pub(crate) fn terminal_size() -> Option<(usize, usize)> {
+    terminal_size::terminal_size().map(|(width, height)| (width.0 as usize, height.0 as usize))
+}
+
+/// Build the sequence redrawing the screen with the given lines, cut to fit
+/// the terminal size. The last row is left empty for the new line printed
+/// after the frame.
+pub(crate) fn frame(lines: &[String], size: Option<(usize, usize)>) -> String {
+    let (width, height) = size.unwrap_or((usize::MAX, usize::MAX));
+    let lines: Vec<String> = lines
+        .iter()
+        .take(height.saturating_sub(1))
+        .map(|line| truncate(line, width))
+        .collect();
+    format!(
+        "{}{}{}",
+        HOME,
+        lines.join(&format!("{}\n", CLEAR_LINE)),
+        CLEAR_BELOW
+    )
+}
+
+/// Cut the line to the given number of columns. Escape sequences, such as
+/// colors, don't take any column and are kept, so that a color is always reset.
+pub(crate) fn truncate(line: &str, width: usize) -> String {
+    let mut output = String::new();
+    let mut columns = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            output.push(c);
+            // Copy the sequence up to its final letter
+            for c in chars.by_ref() {
+                output.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else if columns <= width {
+            // Once a character doesn't fit, the following ones are dropped
+            columns += c.width().unwrap_or(0);
+            if columns <= width {
+                output.push(c);
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_fits_terminal() {
+        let lines = ["abcdef", "ghi", "jkl"].map(String::from);
+        assert_eq!(frame(&lines, Some((4, 3))), "\x1b[Habcd\x1b[K\nghi\x1b[J");
+        assert_eq!(
+            frame(&lines, None),
+            "\x1b[Habcdef\x1b[K\nghi\x1b[K\njkl\x1b[J"
+        );
+    }
+
+    #[test]
+    fn truncate_colors() {
+        assert_eq!(truncate("ab\x1b[32mcde\x1b[0mf", 3), "ab\x1b[32mc\x1b[0m");
+        assert_eq!(truncate("请求x", 3), "请");
+    }
+}
@@ -0,0 +1,184 @@
+use metrics::Unit;
+
+use serde::Deserialize;
+
+use crate::Value;
+
+/// Prefixes used to scale byte sizes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Prefixes {
+    /// Powers of 1024: KiB, MiB, GiB, TiB
+    #[default]
+    Binary,
+    /// Powers of 1000: kB, MB, GB, TB
+    Decimal,
+}
+
+/// How values are formatted according to their [`Unit`]: byte sizes are
+/// scaled to KiB, MiB, ..., durations to ns, µs, ms or s, bit rates to kb/s,
+/// Mb/s, ... and counts get SI suffixes (k, M, G, ...).
+///
+/// ```
+/// use metrics::Unit;
+/// use metrics_exporter_cli::{NumberFormat, Prefixes, Value};
+///
+/// let format = NumberFormat::new();
+/// assert_eq!(format.format(&Value::Int(1536), Some(Unit::Bytes)), "1.5KiB");
+/// assert_eq!(format.format(&Value::F64(0.0123), Some(Unit::Seconds)), "12.3ms");
+/// let format = format.with_precision(1).with_prefixes(Prefixes::Decimal);
+/// assert_eq!(format.format(&Value::Int(1536), Some(Unit::Bytes)), "1.5kB");
+/// assert_eq!(format.format(&Value::Int(1234567), Some(Unit::Count)), "1.2M");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Maximum number of decimals. Trailing zeros are not displayed.
+    precision: usize,
+    prefixes: Prefixes,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            precision: 2,
+            prefixes: Prefixes::Binary,
+        }
+    }
+}
+
+const SI_PREFIXES: &[&str] = &["", "k", "M", "G", "T", "P"];
+const BINARY_BYTES: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+const DECIMAL_BYTES: &[&str] = &["B", "kB", "MB", "GB", "TB", "PB"];
+const BIT_RATES: &[&str] = &["b/s", "kb/s", "Mb/s", "Gb/s", "Tb/s", "Pb/s"];
+
+impl NumberFormat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of decimals. Defaults to 2.
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Set the prefixes of byte sizes. Defaults to [`Prefixes::Binary`].
+    pub fn with_prefixes(mut self, prefixes: Prefixes) -> Self {
+        self.prefixes = prefixes;
+        self
+    }
+
+    /// Format a value of the given unit. Values without unit are only rounded.
+    pub fn format(&self, value: &Value, unit: Option<Unit>) -> String {
+        let x = match (value, unit) {
+            (Value::Empty, _) => return value.to_string(),
+            (Value::Int(x), None) => return x.to_string(),
+            (Value::Int(x), Some(_)) => *x as f64,
+            (Value::F64(x), _) => *x,
+        };
+        let Some(unit) = unit else {
+            return self.round(x);
+        };
+        match unit {
+            Unit::Count | Unit::CountPerSecond => self.scale(x, 1000.0, SI_PREFIXES),
+            Unit::Percent => format!("{}%", self.round(x)),
+            Unit::Seconds => self.duration(x),
+            Unit::Milliseconds => self.duration(x * 1e-3),
+            Unit::Microseconds => self.duration(x * 1e-6),
+            Unit::Nanoseconds => self.duration(x * 1e-9),
+            Unit::Bytes => self.bytes(x),
+            Unit::Kibibytes => self.bytes(x * 1024.0),
+            Unit::Mebibytes => self.bytes(x * 1024f64.powi(2)),
+            Unit::Gigibytes => self.bytes(x * 1024f64.powi(3)),
+            Unit::Tebibytes => self.bytes(x * 1024f64.powi(4)),
+            Unit::BitsPerSecond => self.scale(x, 1000.0, BIT_RATES),
+            Unit::KilobitsPerSecond => self.scale(x * 1e3, 1000.0, BIT_RATES),
+            Unit::MegabitsPerSecond => self.scale(x * 1e6, 1000.0, BIT_RATES),
+            Unit::GigabitsPerSecond => self.scale(x * 1e9, 1000.0, BIT_RATES),
+            Unit::TerabitsPerSecond => self.scale(x * 1e12, 1000.0, BIT_RATES),
+        }
+    }
+
+    fn bytes(&self, x: f64) -> String {
+        match self.prefixes {
+            Prefixes::Binary => self.scale(x, 1024.0, BINARY_BYTES),
+            Prefixes::Decimal => self.scale(x, 1000.0, DECIMAL_BYTES),
+        }
+    }
+
+    fn duration(&self, seconds: f64) -> String {
+        let (x, suffix) = match seconds.abs() {
+            s if s >= 1.0 || s == 0.0 => (seconds, "s"),
+            s if s >= 1e-3 => (seconds * 1e3, "ms"),
+            s if s >= 1e-6 => (seconds * 1e6, "µs"),
+            _ => (seconds * 1e9, "ns"),
+        };
+        format!("{}{}", self.round(x), suffix)
+    }
+
+    /// Divide by `factor` until the value is smaller than it, and append the
+    /// matching suffix.
+    fn scale(&self, mut x: f64, factor: f64, suffixes: &[&str]) -> String {
+        let mut i = 0;
+        while x.abs() >= factor && i + 1 < suffixes.len() {
+            x /= factor;
+            i += 1;
+        }
+        format!("{}{}", self.round(x), suffixes[i])
+    }
+
+    fn round(&self, x: f64) -> String {
+        if !x.is_finite() {
+            return x.to_string();
+        }
+        let rounded = format!("{:.*}", self.precision, x);
+        let rounded = if rounded.contains('.') {
+            rounded.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &rounded
+        };
+        match rounded {
+            "-0" => "0".to_string(),
+            rounded => rounded.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: NumberFormat, value: impl Into<Value>, unit: Option<Unit>) -> String {
+        format.format(&value.into(), unit)
+    }
+
+    #[test]
+    fn units() {
+        let f = NumberFormat::new();
+        assert_eq!(format(f, 1073741824i64, Some(Unit::Bytes)), "1GiB");
+        assert_eq!(format(f, 3i64, Some(Unit::Mebibytes)), "3MiB");
+        assert_eq!(format(f, 0.123456789, Some(Unit::Seconds)), "123.46ms");
+        assert_eq!(format(f, 1500i64, Some(Unit::Microseconds)), "1.5ms");
+        assert_eq!(format(f, 12i64, Some(Unit::Nanoseconds)), "12ns");
+        assert_eq!(format(f, 0.25, Some(Unit::Microseconds)), "250ns");
+        assert_eq!(format(f, 2.5, Some(Unit::Microseconds)), "2.5µs");
+        assert_eq!(format(f, 42.123, Some(Unit::Percent)), "42.12%");
+        assert_eq!(format(f, 999i64, Some(Unit::Count)), "999");
+        assert_eq!(format(f, -12340i64, Some(Unit::Count)), "-12.34k");
+        assert_eq!(format(f, 2.5, Some(Unit::MegabitsPerSecond)), "2.5Mb/s");
+        assert_eq!(format(f, 1.0 / 3.0, None), "0.33");
+        assert_eq!(format(f, 1234567i64, None), "1234567");
+        assert_eq!(format(f, -0.001, None), "0");
+        assert_eq!(format(f, Value::Empty, Some(Unit::Bytes)), "-");
+    }
+
+    #[test]
+    fn configuration() {
+        let f = NumberFormat::new()
+            .with_precision(0)
+            .with_prefixes(Prefixes::Decimal);
+        assert_eq!(format(f, 1073741824i64, Some(Unit::Bytes)), "1GB");
+        assert_eq!(format(f, 2i64, Some(Unit::Kibibytes)), "2kB");
+        assert_eq!(format(f, 1.0 / 3.0, None), "0");
+    }
+}